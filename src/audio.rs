@@ -0,0 +1,418 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::{
+    closure::Closure,
+    JsCast,
+};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    AudioBuffer,
+    AudioBufferSourceNode,
+    AudioContext,
+    AudioParam,
+    AudioScheduledSourceNode,
+    GainNode,
+    OscillatorType,
+    Response,
+};
+
+use crate::js;
+
+/// 音の出力先
+/// 全ての音は Bus の GainNode を通ってから、マスターの GainNode へ流れる
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bus {
+    Se,
+    Bgm,
+}
+
+/// 音源
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SoundAsset {
+    pub file: String,   // /resource/sound/ からのファイル名
+    pub bus: Bus,
+    #[serde(default = "SoundAsset::default_volume")]
+    pub volume: f64,    // 音源毎の音量 (音源の大きさを揃えるため)
+    #[serde(default)]
+    pub ducks_bgm: bool,    // 鳴っている間 BGM を下げるか
+}
+impl SoundAsset {
+    fn default_volume() -> f64 {
+        1.0
+    }
+}
+
+/// 音源の一覧と、どの画面でどの BGM を鳴らすか
+/// quastions.json で BGM が指定されていないステージやレベルは、ここで決める
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SoundManifest {
+    pub sounds: HashMap<String, SoundAsset>,
+
+    pub select_bgm: String,                     // ステージ選択の BGM
+    #[serde(default)]
+    pub level_bgm: HashMap<String, String>,     // レベル毎のステージ選択の BGM
+    pub quastion_bgm: String,                   // ステージの BGM
+    #[serde(default)]
+    pub stage_bgm: HashMap<String, String>,     // ステージ毎の BGM
+}
+impl SoundManifest {
+    pub fn level_bgm(&self, stage_level: i32) -> &str {
+        self.level_bgm.get(&stage_level.to_string()).unwrap_or(&self.select_bgm)
+    }
+
+    pub fn stage_bgm(&self, key: &str) -> &str {
+        self.stage_bgm.get(key).unwrap_or(&self.quastion_bgm)
+    }
+}
+
+/// 音の鳴らし方
+#[derive(Clone, Copy, Debug)]
+pub struct PlayOptions {
+    pub is_loop: bool,
+    pub fade_in: f64,       // 音量を 0 から上げていく秒数
+    pub ducks_bgm: bool,    // 鳴っている間 BGM を下げるか
+    pub playback_rate: f64, // 再生速度 (2.0 で 1 オクターブ上がる)
+}
+impl Default for PlayOptions {
+    fn default() -> Self {
+        Self {
+            is_loop: false,
+            fade_in: 0.0,
+            ducks_bgm: false,
+            playback_rate: 1.0,
+        }
+    }
+}
+
+/// 音声ファイルを使わずに鳴らす短い音
+#[derive(Clone, Copy, Debug)]
+pub struct Tone {
+    pub from_frequency: f32,    // 鳴り始めの周波数 (Hz)
+    pub to_frequency: f32,      // 鳴り終わりの周波数 (Hz)
+    pub volume: f32,
+    pub delay: f64,             // 鳴らし始めるまでの秒数
+    pub duration: f64,          // 鳴らす秒数
+}
+
+/// 読み込み中の音の状態
+enum Track {
+    Loading(Vec<(Bus, PlayOptions)>),   // 読み込み終わったら再生する
+    Loaded(AudioBuffer),
+    Failed,
+}
+
+/// 再生中の音
+struct Voice {
+    id: u64,
+    name: String,
+    bus: Bus,
+    source: AudioBufferSourceNode,
+    gain: GainNode,
+}
+
+/// AudioParam の変化を AudioContext の時計で予約する
+/// setTimeout と違い、タブの負荷に関係なく時間通りに音量が変わる
+struct Scheduler;
+impl Scheduler {
+    /// 予約済みの変化を取り消して、now の値から duration 秒かけて value にする
+    fn ramp(param: &AudioParam, now: f64, value: f32, duration: f64) {
+        let _ = param.cancel_scheduled_values(now);
+        let _ = param.set_value_at_time(param.value(), now);
+        let _ = param.linear_ramp_to_value_at_time(value, now + duration.max(0.0));
+    }
+
+    /// now から attack 秒で value まで下げ、hold 秒保ってから release 秒で 1.0 に戻す
+    fn duck(param: &AudioParam, now: f64, value: f32, attack: f64, hold: f64, release: f64) {
+        Self::ramp(param, now, value, attack);
+        let _ = param.set_value_at_time(value, now + attack + hold);
+        let _ = param.linear_ramp_to_value_at_time(1.0, now + attack + hold + release);
+    }
+}
+
+struct MixerState {
+    context: AudioContext,
+    master: GainNode,
+    buses: HashMap<Bus, GainNode>,
+    bgm_duck: GainNode,     // 効果音に合わせて BGM を下げるための GainNode (BGM の Bus の後ろ)
+    tracks: HashMap<String, Track>,
+    track_volumes: HashMap<String, f32>,
+    voices: Vec<Voice>,
+    next_voice_id: u64,
+}
+
+/// Web Audio API で音を鳴らす
+/// 音声ファイルは一度だけデコードして AudioBuffer を使い回すので、同じ音を重ねて鳴らせる
+/// AudioContext が作れないブラウザでは何も鳴らさない
+#[derive(Clone)]
+pub struct Mixer {
+    state: Option<Rc<RefCell<MixerState>>>,
+}
+impl Mixer {
+    // BGM を下げる時の音量と、下げる・戻す秒数
+    const DUCK_VOLUME: f32 = 0.3;
+    const DUCK_ATTACK_SECONDS: f64 = 0.05;
+    const DUCK_RELEASE_SECONDS: f64 = 0.5;
+
+    pub fn new() -> Self {
+        let state = MixerState::new().map_err(|e| js::console_log!("failed to create AudioContext E:{:?}", e)).ok();
+
+        Self {
+            state: state.map(|state| Rc::new(RefCell::new(state))),
+        }
+    }
+
+    /// url の音声ファイルを name として読み込む、既に読み込んでいれば何もしない
+    pub fn load(&self, name: &str, url: &str) {
+        let state = match &self.state {
+            Some(state) => state.clone(),
+            None => return,
+        };
+        if state.borrow().tracks.contains_key(name) {
+            return;
+        }
+        state.borrow_mut().tracks.insert(name.to_string(), Track::Loading(vec![]));
+
+        let name = name.to_string();
+        let url = url.to_string();
+        wasm_bindgen_futures::spawn_local(async move {
+            let context = state.borrow().context.clone();
+            let track = match Self::fetch_and_decode(&context, &url).await {
+                Ok(buffer) => Track::Loaded(buffer),
+                Err(e) => {
+                    js::console_log!("failed to load {} E:{:?}", url, e);
+                    Track::Failed
+                },
+            };
+
+            // 読み込み中に再生しようとしていた音を鳴らす
+            let waiting = match state.borrow_mut().tracks.insert(name.clone(), track) {
+                Some(Track::Loading(waiting)) => waiting,
+                _ => vec![],
+            };
+            for (bus, options) in waiting {
+                state.borrow_mut().start(&state, &name, bus, options);
+            }
+        });
+    }
+
+    async fn fetch_and_decode(context: &AudioContext, url: &str) -> Result<AudioBuffer, wasm_bindgen::JsValue> {
+        let window = web_sys::window().expect("no global `window` exist");
+        let response: Response = JsFuture::from(window.fetch_with_str(url)).await?.dyn_into()?;
+        let array_buffer = JsFuture::from(response.array_buffer()?).await?.dyn_into()?;
+        let buffer = JsFuture::from(context.decode_audio_data(&array_buffer)?).await?;
+
+        buffer.dyn_into()
+    }
+
+    /// name の音を bus へ鳴らす、再生中の同じ音は止めずに重ねる
+    pub fn play(&self, name: &str, bus: Bus, options: PlayOptions) {
+        if let Some(state) = &self.state {
+            state.borrow_mut().start(state, name, bus, options);
+        }
+    }
+
+    /// tone を正弦波で bus へ鳴らす
+    pub fn play_tone(&self, tone: Tone, bus: Bus) {
+        if let Some(state) = &self.state {
+            if let Err(e) = state.borrow().start_tone(tone, bus) {
+                js::console_log!("failed to play tone E:{:?}", e);
+            }
+        }
+    }
+
+    /// bus で鳴っている音を duration 秒かけて消す
+    pub fn fade_out(&self, bus: Bus, duration: f64) {
+        if let Some(state) = &self.state {
+            state.borrow_mut().fade_out_where(|voice_bus| voice_bus == bus, duration);
+        }
+    }
+
+    /// 鳴っている音を全てすぐに止める
+    pub fn stop_all(&self) {
+        if let Some(state) = &self.state {
+            state.borrow_mut().fade_out_where(|_| true, 0.0);
+        }
+    }
+
+    /// 全体の音量 (0.0 ~ 1.0) を変える
+    pub fn set_master_volume(&self, volume: f64) {
+        if let Some(state) = &self.state {
+            state.borrow().master.gain().set_value(volume as f32);
+        }
+    }
+
+    /// bus の音量 (0.0 ~ 1.0) を変える
+    pub fn set_bus_volume(&self, bus: Bus, volume: f64) {
+        if let Some(state) = &self.state {
+            state.borrow().buses[&bus].gain().set_value(volume as f32);
+        }
+    }
+
+    /// name の音の音量 (0.0 ~ 1.0) を変える、再生中の音にも反映する
+    pub fn set_track_volume(&self, name: &str, volume: f64) {
+        if let Some(state) = &self.state {
+            let mut state = state.borrow_mut();
+            state.track_volumes.insert(name.to_string(), volume as f32);
+            for voice in state.voices.iter().filter(|voice| voice.name == name) {
+                voice.gain.gain().set_value(volume as f32);
+            }
+        }
+    }
+}
+
+impl MixerState {
+    fn new() -> Result<Self, wasm_bindgen::JsValue> {
+        let context = AudioContext::new()?;
+        let master = context.create_gain()?;
+        master.connect_with_audio_node(&context.destination())?;
+        let bgm_duck = context.create_gain()?;
+        bgm_duck.connect_with_audio_node(&master)?;
+
+        let mut buses = HashMap::new();
+        for bus in [Bus::Se, Bus::Bgm] {
+            let gain = context.create_gain()?;
+            match bus {
+                Bus::Bgm => gain.connect_with_audio_node(&bgm_duck)?,
+                Bus::Se => gain.connect_with_audio_node(&master)?,
+            };
+            buses.insert(bus, gain);
+        }
+
+        Ok(Self {
+            context,
+            master,
+            buses,
+            bgm_duck,
+            tracks: HashMap::new(),
+            track_volumes: HashMap::new(),
+            voices: vec![],
+            next_voice_id: 0,
+        })
+    }
+
+    fn start(&mut self, shared: &Rc<RefCell<MixerState>>, name: &str, bus: Bus, options: PlayOptions) {
+        // ブラウザの自動再生制限で止まっていれば、ユーザー操作の中で再開する
+        let _ = self.context.resume();
+
+        let buffer = match self.tracks.get_mut(name) {
+            Some(Track::Loaded(buffer)) => buffer.clone(),
+            Some(Track::Loading(waiting)) => {
+                // ループする音 (BGM) は読み込み後に鳴らす、効果音は遅れて鳴っても意味がないので捨てる
+                if options.is_loop {
+                    waiting.push((bus, options));
+                }
+                return;
+            },
+            _ => {
+                js::console_log!("sound {} is not loaded", name);
+                return;
+            },
+        };
+
+        match self.create_voice(name, &buffer, bus, options) {
+            Ok(voice) => {
+                // 鳴り終わった音は再生中の一覧から外す
+                let id = voice.id;
+                let weak = Rc::downgrade(shared);
+                let on_ended = Closure::once_into_js(move || {
+                    if let Some(state) = weak.upgrade() {
+                        state.borrow_mut().voices.retain(|voice| voice.id != id);
+                    }
+                });
+                AudioScheduledSourceNode::set_onended(&voice.source, Some(on_ended.unchecked_ref()));
+
+                if options.ducks_bgm {
+                    Scheduler::duck(
+                        &self.bgm_duck.gain(),
+                        self.context.current_time(),
+                        Mixer::DUCK_VOLUME,
+                        Mixer::DUCK_ATTACK_SECONDS,
+                        buffer.duration(),
+                        Mixer::DUCK_RELEASE_SECONDS,
+                    );
+                }
+
+                self.voices.push(voice);
+                js::console_log!("play {:?}", name);
+            },
+            Err(e) => js::console_log!("failed to play {} E:{:?}", name, e),
+        }
+    }
+
+    /// source -> gain (音毎の音量) -> bus と繋いで鳴らす
+    fn create_voice(&mut self, name: &str, buffer: &AudioBuffer, bus: Bus, options: PlayOptions) -> Result<Voice, wasm_bindgen::JsValue> {
+        let volume = self.track_volumes.get(name).copied().unwrap_or(1.0);
+        let gain = self.context.create_gain()?;
+        if 0.0 < options.fade_in {
+            gain.gain().set_value(0.0);
+            Scheduler::ramp(&gain.gain(), self.context.current_time(), volume, options.fade_in);
+        } else {
+            gain.gain().set_value(volume);
+        }
+        gain.connect_with_audio_node(&self.buses[&bus])?;
+
+        let source = self.context.create_buffer_source()?;
+        source.set_buffer(Some(buffer));
+        source.set_loop(options.is_loop);
+        source.playback_rate().set_value(options.playback_rate as f32);
+        source.connect_with_audio_node(&gain)?;
+        source.start()?;
+
+        self.next_voice_id += 1;
+        Ok(Voice {
+            id: self.next_voice_id,
+            name: name.to_string(),
+            bus,
+            source,
+            gain,
+        })
+    }
+
+    /// oscillator -> gain (音量の包絡線) -> bus と繋いで、鳴らし終わったら止める
+    fn start_tone(&self, tone: Tone, bus: Bus) -> Result<(), wasm_bindgen::JsValue> {
+        let _ = self.context.resume();
+        let start = self.context.current_time() + tone.delay;
+        let end = start + tone.duration;
+
+        let gain = self.context.create_gain()?;
+        gain.gain().set_value_at_time(0.0, start)?;
+        gain.gain().linear_ramp_to_value_at_time(tone.volume, start + tone.duration * 0.1)?;
+        gain.gain().linear_ramp_to_value_at_time(0.0, end)?;
+        gain.connect_with_audio_node(&self.buses[&bus])?;
+
+        let oscillator = self.context.create_oscillator()?;
+        oscillator.set_type(OscillatorType::Sine);
+        oscillator.frequency().set_value_at_time(tone.from_frequency, start)?;
+        oscillator.frequency().exponential_ramp_to_value_at_time(tone.to_frequency, end)?;
+        oscillator.connect_with_audio_node(&gain)?;
+        oscillator.start_with_when(start)?;
+        oscillator.stop_with_when(end)?;
+
+        Ok(())
+    }
+
+    /// predicate が true を返す Bus の音を duration 秒かけて消して止める
+    fn fade_out_where<F: Fn(Bus) -> bool>(&mut self, predicate: F, duration: f64) {
+        // 読み込み中で鳴るのを待っている BGM も止める
+        for track in self.tracks.values_mut() {
+            if let Track::Loading(waiting) = track {
+                waiting.retain(|(bus, _)| !predicate(*bus));
+            }
+        }
+
+        let now = self.context.current_time();
+        let (stopped, playing): (Vec<Voice>, Vec<Voice>) = self.voices.drain(..).partition(|voice| predicate(voice.bus));
+        for voice in stopped {
+            Scheduler::ramp(&voice.gain.gain(), now, 0.0, duration);
+            let _ = AudioScheduledSourceNode::stop_with_when(&voice.source, now + duration);
+            js::console_log!("fade out {:?}", voice.name);
+        }
+        self.voices = playing;
+    }
+}
@@ -0,0 +1,11 @@
+//! 進行状況の同期を確認するための開発用モックサーバー
+//! cargo run --features sync --bin mock_sync_server [address]
+
+fn main() {
+    let address = std::env::args().nth(1).unwrap_or_else(|| "127.0.0.1:8787".to_string());
+    let listener = std::net::TcpListener::bind(&address).expect("failed to bind address");
+
+    println!("mock sync server listening on http://{}", address);
+    println!("open index.html?sync_endpoint=http://{}&sync_id=<user id> to synchronize.", address);
+    onsen::sync::mock_server::serve(listener);
+}
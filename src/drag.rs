@@ -0,0 +1,223 @@
+use wasm_bindgen::JsCast;
+use web_sys::{
+    CanvasRenderingContext2d,
+    HtmlCanvasElement,
+    HtmlImageElement,
+};
+
+/// 温泉マークのドラッグ
+/// 座標は css からではなく PointerEvent から取り、ドラッグを始めた pointer の移動だけを追う
+#[derive(Clone, Copy, Debug)]
+pub struct DragController {
+    pointer_id: i32,
+    x: f64,
+    y: f64,
+}
+impl DragController {
+    pub fn start(pointer_id: i32, x: f64, y: f64) -> Self {
+        Self {
+            pointer_id,
+            x,
+            y,
+        }
+    }
+
+    /// pointer_id がドラッグ中の pointer なら座標を更新して true を返す
+    pub fn move_to(&mut self, pointer_id: i32, x: f64, y: f64) -> bool {
+        if pointer_id != self.pointer_id {
+            return false;
+        }
+
+        self.x = x;
+        self.y = y;
+        true
+    }
+
+    pub fn position(&self) -> (f64, f64) {
+        (self.x, self.y)
+    }
+}
+
+/// 画像の不透明な部分 (線が描かれている所)
+/// 温泉マークの線はベン図のように重なっているので、矩形ではなく画素で当たりを判定する
+#[derive(Clone, Debug)]
+pub struct HitMask {
+    width: usize,
+    height: usize,
+    opaque: Vec<bool>,
+}
+impl HitMask {
+    // これより不透明な画素を線とみなす (縁のアンチエイリアスは含めない)
+    const ALPHA_THRESHOLD: u8 = 32;
+
+    /// 読み込み済みの image の透明度から作る、まだ読み込まれていなければ None
+    pub fn from_image(image: &HtmlImageElement) -> Option<Self> {
+        let width = image.natural_width();
+        let height = image.natural_height();
+        if !image.complete() || 0 == width || 0 == height {
+            return None;
+        }
+
+        let document = web_sys::window()?.document()?;
+        let canvas: HtmlCanvasElement = document.create_element("canvas").ok()?.dyn_into().ok()?;
+        canvas.set_width(width);
+        canvas.set_height(height);
+        let context: CanvasRenderingContext2d = canvas.get_context("2d").ok()??.dyn_into().ok()?;
+        context.draw_image_with_html_image_element(image, 0.0, 0.0).ok()?;
+        let data = context.get_image_data(0.0, 0.0, width as f64, height as f64).ok()?.data();
+
+        // RGBA の A だけを見る
+        Some(Self {
+            width: width as usize,
+            height: height as usize,
+            opaque: data.chunks(4).map(|rgba| Self::ALPHA_THRESHOLD < rgba[3]).collect(),
+        })
+    }
+
+    /// 画像内の位置 (u, v は 0.0 ~ 1.0) が不透明か
+    pub fn is_opaque(&self, u: f64, v: f64) -> bool {
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return false;
+        }
+
+        let x = (u * self.width as f64) as usize;
+        let y = (v * self.height as f64) as usize;
+        self.opaque[y * self.width + x]
+    }
+}
+
+/// 当たり判定をする対象
+pub struct HitTarget<'a> {
+    pub index: usize,
+    pub left: f64,
+    pub top: f64,
+    pub width: f64,
+    pub height: f64,
+    pub mask: Option<&'a HitMask>,  // 無ければ矩形で判定する
+}
+impl HitTarget<'_> {
+    /// (x, y) が当たっているか
+    fn contains(&self, x: f64, y: f64) -> bool {
+        if self.width <= 0.0 || self.height <= 0.0 {
+            return false;
+        }
+
+        let u = (x - self.left) / self.width;
+        let v = (y - self.top) / self.height;
+        match self.mask {
+            Some(mask) => mask.is_opaque(u, v),
+            None => (0.0..1.0).contains(&u) && (0.0..1.0).contains(&v),
+        }
+    }
+
+    /// (x, y) から中心までの距離の二乗
+    fn distance_from_center(&self, x: f64, y: f64) -> f64 {
+        let dx = x - (self.left + self.width / 2.0);
+        let dy = y - (self.top + self.height / 2.0);
+        dx * dx + dy * dy
+    }
+}
+
+/// ドロップした結果
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HitResult {
+    Sen(usize),
+    NoTarget,
+}
+
+/// (x, y) に当たっている対象を返す
+/// 複数に当たっていれば (線の重なり) 中心が一番近いものにする
+pub fn hit_test(targets: &[HitTarget], x: f64, y: f64) -> HitResult {
+    targets.iter()
+        .filter(|target| target.contains(x, y))
+        .min_by(|a, b| a.distance_from_center(x, y).partial_cmp(&b.distance_from_center(x, y)).unwrap_or(std::cmp::Ordering::Equal))
+        .map_or(HitResult::NoTarget, |target| HitResult::Sen(target.index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2x2 の画像で、左上と右下だけが不透明
+    fn diagonal_mask() -> HitMask {
+        HitMask {
+            width: 2,
+            height: 2,
+            opaque: vec![true, false, false, true],
+        }
+    }
+
+    fn target(index: usize, left: f64, top: f64, mask: Option<&HitMask>) -> HitTarget<'_> {
+        HitTarget {
+            index,
+            left,
+            top,
+            width: 10.0,
+            height: 10.0,
+            mask,
+        }
+    }
+
+    #[test]
+    fn move_to_ignores_other_pointers() {
+        let mut drag = DragController::start(1, 0.0, 0.0);
+
+        assert!(!drag.move_to(2, 5.0, 5.0));
+        assert_eq!(drag.position(), (0.0, 0.0));
+        assert!(drag.move_to(1, 3.0, 4.0));
+        assert_eq!(drag.position(), (3.0, 4.0));
+    }
+
+    #[test]
+    fn is_opaque_reads_pixels() {
+        let mask = diagonal_mask();
+
+        assert!(mask.is_opaque(0.25, 0.25));
+        assert!(!mask.is_opaque(0.75, 0.25));
+        assert!(!mask.is_opaque(0.25, 0.75));
+        assert!(mask.is_opaque(0.75, 0.75));
+    }
+
+    #[test]
+    fn is_opaque_outside_image_is_false() {
+        let mask = diagonal_mask();
+
+        assert!(!mask.is_opaque(-0.1, 0.25));
+        assert!(!mask.is_opaque(0.25, 1.0));
+        assert!(!mask.is_opaque(1.0, 1.0));
+    }
+
+    #[test]
+    fn hit_test_without_mask_uses_rect() {
+        let targets = [target(0, 0.0, 0.0, None), target(1, 20.0, 0.0, None)];
+
+        assert_eq!(hit_test(&targets, 5.0, 5.0), HitResult::Sen(0));
+        assert_eq!(hit_test(&targets, 25.0, 5.0), HitResult::Sen(1));
+        assert_eq!(hit_test(&targets, 15.0, 5.0), HitResult::NoTarget);
+    }
+
+    #[test]
+    fn hit_test_skips_transparent_pixels() {
+        let mask = diagonal_mask();
+        let targets = [target(0, 0.0, 0.0, Some(&mask))];
+
+        assert_eq!(hit_test(&targets, 2.0, 2.0), HitResult::Sen(0));
+        assert_eq!(hit_test(&targets, 8.0, 2.0), HitResult::NoTarget);
+    }
+
+    #[test]
+    fn hit_test_prefers_nearest_center_when_overlapping() {
+        let targets = [target(0, 0.0, 0.0, None), target(1, 5.0, 0.0, None)];
+
+        assert_eq!(hit_test(&targets, 6.0, 5.0), HitResult::Sen(0));
+        assert_eq!(hit_test(&targets, 9.0, 5.0), HitResult::Sen(1));
+    }
+
+    #[test]
+    fn hit_test_ignores_empty_targets() {
+        let mut empty = target(0, 0.0, 0.0, None);
+        empty.width = 0.0;
+
+        assert_eq!(hit_test(&[empty], 0.0, 0.0), HitResult::NoTarget);
+    }
+}
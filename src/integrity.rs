@@ -0,0 +1,113 @@
+use hmac::{
+    Hmac,
+    Mac,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use sha2::Sha256;
+
+/// 署名用の鍵
+/// wasm に埋め込まれているので本気で解析されると偽造できるが、手で JSON を書き換えた程度の改ざんは検出できる
+const SIGNING_KEY: &[u8] = b"On!Sen! save data / bass_clef_";
+
+/// セーブデータを包む封筒
+#[derive(Debug, Deserialize, Serialize)]
+struct Envelope {
+    payload: String,
+    signature: String,
+}
+
+/// 読み込んだセーブデータの検証結果
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verification {
+    Verified,   // 署名が一致した
+    Modified,   // 署名が一致しない (書き換えられている)
+    Unsigned,   // 封筒に入っていない (署名を付ける前の古いセーブデータ)
+}
+
+fn sign(payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(SIGNING_KEY).unwrap();
+    mac.update(payload.as_bytes());
+
+    mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// payload を署名付きの封筒に入れた文字列を返す
+pub fn seal(payload: &str) -> String {
+    serde_json::to_string(&Envelope {
+        payload: payload.to_string(),
+        signature: sign(payload),
+    }).unwrap()
+}
+
+/// seal した文字列から payload を取り出して、検証結果と共に返す
+/// 封筒に入っていなければ data をそのまま payload とする
+pub fn open(data: &str) -> (String, Verification) {
+    match serde_json::from_str::<Envelope>(data) {
+        Ok(envelope) => {
+            let verification = if sign(&envelope.payload) == envelope.signature {
+                Verification::Verified
+            } else {
+                Verification::Modified
+            };
+
+            (envelope.payload, verification)
+        },
+        Err(_) => (data.to_string(), Verification::Unsigned),
+    }
+}
+
+/// 署名の無いセーブデータを古いものとして信用する期限 (2027-01-01 00:00 UTC, ミリ秒)
+/// 目印は消されると分からないので、これを過ぎたら署名の無いセーブデータは全て書き換えられたものとみなす
+pub const UNSIGNED_ACCEPTED_UNTIL: f64 = 1_798_761_600_000.0;
+
+/// 検証結果から、書き換えられたものとみなすかを返す
+/// has_sealed_marker は署名付きで保存したことがあるかの目印、now は今の時刻 (ミリ秒)
+pub fn is_modified(verification: Verification, has_sealed_marker: bool, now: f64) -> bool {
+    match verification {
+        Verification::Verified => false,
+        Verification::Modified => true,
+        Verification::Unsigned => has_sealed_marker || UNSIGNED_ACCEPTED_UNTIL <= now,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BEFORE_DEADLINE: f64 = UNSIGNED_ACCEPTED_UNTIL - 1.0;
+
+    #[test]
+    fn sealed_payload_is_verified() {
+        let (payload, verification) = open(&seal(r#"{"a":1}"#));
+        assert_eq!(r#"{"a":1}"#, payload);
+        assert_eq!(Verification::Verified, verification);
+        assert!(!is_modified(verification, true, BEFORE_DEADLINE));
+    }
+
+    #[test]
+    fn rewritten_payload_is_modified() {
+        let sealed = seal(r#"{"a":1}"#).replace(r#"{\"a\":1}"#, r#"{\"a\":2}"#);
+        let (payload, verification) = open(&sealed);
+        assert_eq!(r#"{"a":2}"#, payload);
+        assert_eq!(Verification::Modified, verification);
+        assert!(is_modified(verification, false, BEFORE_DEADLINE));
+    }
+
+    #[test]
+    fn unsigned_with_marker_is_modified() {
+        let (_, verification) = open(r#"{"a":1}"#);
+        assert_eq!(Verification::Unsigned, verification);
+        assert!(is_modified(verification, true, BEFORE_DEADLINE));
+    }
+
+    #[test]
+    fn unsigned_without_marker_is_trusted_only_before_deadline() {
+        // 目印を消して署名の無いセーブデータを置いた場合
+        let (_, verification) = open(r#"{"a":1}"#);
+        assert!(!is_modified(verification, false, BEFORE_DEADLINE));
+        assert!(is_modified(verification, false, UNSIGNED_ACCEPTED_UNTIL));
+    }
+}
@@ -85,6 +85,74 @@ pub mod dom {
 
         param.get(name)
     }
+
+    /// 確認のダイアログを出して、OK が押されたかを返す
+    pub fn confirm(message: &str) -> bool {
+        let window = web_sys::window().expect("no global `window` exist");
+        window.confirm_with_message(message).unwrap_or(false)
+    }
+
+    /// キーが押された時に f(key) を呼ぶ (key は KeyboardEvent.key, "ArrowLeft" や "1" など)
+    /// ボタンや入力欄にフォーカスがある時はブラウザの操作を優先して呼ばない
+    pub fn add_key_listener<F>(mut f: F)
+    where
+        F: FnMut(String) + 'static,
+    {
+        let window = web_sys::window().expect("no global `window` exist");
+        let closure = Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
+            // ブラウザのショートカットは邪魔しない
+            if event.ctrl_key() || event.meta_key() || event.alt_key() {
+                return;
+            }
+            let tag_name = event.target()
+                .and_then(|target| target.dyn_into::<web_sys::Element>().ok())
+                .map(|element| element.tag_name())
+                .unwrap_or_default();
+            // 入力欄ではどのキーも入力に使う
+            if ["INPUT", "SELECT", "TEXTAREA"].contains(&tag_name.as_str()) {
+                return;
+            }
+            // ボタンやリンクでは Enter/Space でクリックさせて、それ以外のキーは受け取る
+            let key = event.key();
+            if ["A", "BUTTON"].contains(&tag_name.as_str()) && ("Enter" == key || " " == key) {
+                return;
+            }
+
+            // 矢印キーやスペースでのスクロールをさせない
+            if key.starts_with("Arrow") || " " == key || "Backspace" == key {
+                event.prevent_default();
+            }
+            f(key);
+        }) as Box<dyn FnMut(web_sys::KeyboardEvent)>);
+
+        window.add_event_listener_with_callback("keydown", closure.as_ref().unchecked_ref()).unwrap();
+        // ページが開いている間はずっと監視するので開放しない
+        closure.forget();
+    }
+
+    /// img の読み込みに失敗した時に f(src) を呼び、代わりのパスが返ってきたらそれを読み込み直す
+    /// error はバブリングしないので、キャプチャで全ての img のものを受け取る
+    pub fn add_image_error_listener<F>(mut f: F)
+    where
+        F: FnMut(&str) -> Option<String> + 'static,
+    {
+        let window = web_sys::window().expect("no global `window` exist");
+        let closure = Closure::wrap(Box::new(move |event: web_sys::Event| {
+            let image = match event.target().and_then(|target| target.dyn_into::<HtmlImageElement>().ok()) {
+                Some(image) => image,
+                None => return,
+            };
+            // src プロパティは絶対 URL になるので、設定したままの属性を見る
+            if let Some(fallback) = image.get_attribute("src").and_then(|src| f(&src)) {
+                crate::js::console_log!("failed to load {:?}. use {}", image.get_attribute("src"), fallback);
+                image.set_src(&fallback);
+            }
+        }) as Box<dyn FnMut(web_sys::Event)>);
+
+        window.add_event_listener_with_callback_and_bool("error", closure.as_ref().unchecked_ref(), true).unwrap();
+        // ページが開いている間はずっと監視するので開放しない
+        closure.forget();
+    }
 }
 
 pub mod fs {
@@ -108,30 +176,6 @@ pub mod fs {
         // WARNING: str を static として保持しておくけど、おそらくプログラム終了時に開放されるので問題ない?
         stringreader::StringReader::new( Box::leak(data.into_boxed_str()) )
     }
-
-    /// データを LocalStorage でやり取りするための getter (ファイルの代わり)
-    pub fn read_storage<'a>(name: String) -> Option<stringreader::StringReader<'a>> {
-        let window = web_sys::window().expect("no global `window` exist");
-        let storage = match window.local_storage().unwrap() {
-            Some(storage) => storage,
-            None => return None,
-        };
-
-        let data = storage.get_item(&name).unwrap();
-
-        // WARNING: str を static として保持しておくけど、おそらくプログラム終了時に開放されるので問題ない?
-        match data {
-            Some(data) => Some(stringreader::StringReader::new( Box::leak(data.into_boxed_str()) )),
-            None => None,
-        }
-    }
-
-    /// データを LocalStorage でやり取りするための setter (ファイルの代わり)
-    pub fn write_storage(name: String, data: String) {
-        let window = web_sys::window().expect("no global `window` exist");
-        let storage = window.local_storage().unwrap().expect("no local storage exist. don't write.");
-        storage.set_item(&name, &data).unwrap();
-    }
 }
 
 pub mod time {
@@ -142,4 +186,18 @@ pub mod time {
         /// どうやら Rust::std::time::Instant::now は未実装っぽいので JavaScript からもらう
         pub fn now() -> JsValue;
     }
+
+    /// 今の処理と描画を終えてから f を呼ぶ (時間のかかる処理を分けて、画面を止めないようにする)
+    pub fn set_timeout<F: FnOnce() + 'static>(f: F) {
+        let closure = Closure::once_into_js(f);
+        web_sys::window().expect("no global `window` exist")
+            .set_timeout_with_callback_and_timeout_and_arguments_0(closure.unchecked_ref(), 0)
+            .unwrap();
+    }
+
+    /// now の ms を "年/月/日" の文字列にする
+    pub fn format_date(ms: f64) -> String {
+        let date = js_sys::Date::new(&JsValue::from_f64(ms));
+        format!("{}/{}/{}", date.get_full_year(), date.get_month() + 1, date.get_date())
+    }
 }
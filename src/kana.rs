@@ -0,0 +1,319 @@
+//! ローマ字とかなの変換
+//! 温泉名は SenOp の名前 ("on", "off", "not", "or", "and", "oro", "ando") をつなげたものなので
+//! 日本語として読めない子音の連続も、必ず決まったひらがなになるようにする
+
+/// ローマ字 -> ひらがな の表 (長い方から一致させる)
+const ROMAJI_TABLE: &[(&str, &str)] = &[
+    // 3文字
+    ("kya", "きゃ"), ("kyu", "きゅ"), ("kyo", "きょ"),
+    ("gya", "ぎゃ"), ("gyu", "ぎゅ"), ("gyo", "ぎょ"),
+    ("sha", "しゃ"), ("shi", "し"), ("shu", "しゅ"), ("she", "しぇ"), ("sho", "しょ"),
+    ("sya", "しゃ"), ("syu", "しゅ"), ("syo", "しょ"),
+    ("zya", "じゃ"), ("zyu", "じゅ"), ("zyo", "じょ"),
+    ("jya", "じゃ"), ("jyu", "じゅ"), ("jyo", "じょ"),
+    ("cha", "ちゃ"), ("chi", "ち"), ("chu", "ちゅ"), ("che", "ちぇ"), ("cho", "ちょ"),
+    ("tya", "ちゃ"), ("tyu", "ちゅ"), ("tyo", "ちょ"),
+    ("tsu", "つ"),
+    ("nya", "にゃ"), ("nyu", "にゅ"), ("nyo", "にょ"),
+    ("hya", "ひゃ"), ("hyu", "ひゅ"), ("hyo", "ひょ"),
+    ("bya", "びゃ"), ("byu", "びゅ"), ("byo", "びょ"),
+    ("pya", "ぴゃ"), ("pyu", "ぴゅ"), ("pyo", "ぴょ"),
+    ("mya", "みゃ"), ("myu", "みゅ"), ("myo", "みょ"),
+    ("rya", "りゃ"), ("ryu", "りゅ"), ("ryo", "りょ"),
+    // 2文字
+    ("ka", "か"), ("ki", "き"), ("ku", "く"), ("ke", "け"), ("ko", "こ"),
+    ("ga", "が"), ("gi", "ぎ"), ("gu", "ぐ"), ("ge", "げ"), ("go", "ご"),
+    ("sa", "さ"), ("si", "し"), ("su", "す"), ("se", "せ"), ("so", "そ"),
+    ("za", "ざ"), ("zi", "じ"), ("zu", "ず"), ("ze", "ぜ"), ("zo", "ぞ"),
+    ("ja", "じゃ"), ("ji", "じ"), ("ju", "じゅ"), ("je", "じぇ"), ("jo", "じょ"),
+    ("ta", "た"), ("ti", "ち"), ("tu", "つ"), ("te", "て"), ("to", "と"),
+    ("da", "だ"), ("di", "ぢ"), ("du", "づ"), ("de", "で"), ("do", "ど"),
+    ("na", "な"), ("ni", "に"), ("nu", "ぬ"), ("ne", "ね"), ("no", "の"),
+    ("ha", "は"), ("hi", "ひ"), ("hu", "ふ"), ("he", "へ"), ("ho", "ほ"),
+    ("fa", "ふぁ"), ("fi", "ふぃ"), ("fu", "ふ"), ("fe", "ふぇ"), ("fo", "ふぉ"),
+    ("ba", "ば"), ("bi", "び"), ("bu", "ぶ"), ("be", "べ"), ("bo", "ぼ"),
+    ("pa", "ぱ"), ("pi", "ぴ"), ("pu", "ぷ"), ("pe", "ぺ"), ("po", "ぽ"),
+    ("va", "ゔぁ"), ("vi", "ゔぃ"), ("vu", "ゔ"), ("ve", "ゔぇ"), ("vo", "ゔぉ"),
+    ("ma", "ま"), ("mi", "み"), ("mu", "む"), ("me", "め"), ("mo", "も"),
+    ("ya", "や"), ("yu", "ゆ"), ("ye", "いぇ"), ("yo", "よ"),
+    ("ra", "ら"), ("ri", "り"), ("ru", "る"), ("re", "れ"), ("ro", "ろ"),
+    ("la", "ら"), ("li", "り"), ("lu", "る"), ("le", "れ"), ("lo", "ろ"),
+    ("wa", "わ"), ("wi", "うぃ"), ("we", "うぇ"), ("wo", "を"),
+    // 1文字
+    ("a", "あ"), ("i", "い"), ("u", "う"), ("e", "え"), ("o", "お"),
+];
+
+/// 表で変換できずに余った子音は、元の英語の発音に近いかなにする ("an'd'" -> ど, "o'r'" -> る)
+fn leftover_consonant(consonant: char) -> &'static str {
+    match consonant {
+        'b' => "ぶ",
+        'c' | 'k' | 'q' | 'x' => "く",
+        'd' => "ど",
+        'f' | 'h' => "ふ",
+        'g' => "ぐ",
+        'j' => "じ",
+        'l' | 'r' => "る",
+        'm' => "む",
+        'n' => "ん",
+        'p' => "ぷ",
+        's' => "す",
+        't' => "と",
+        'v' => "ゔ",
+        'w' => "う",
+        'y' => "い",
+        'z' => "ず",
+        _ => "",
+    }
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'i' | 'u' | 'e' | 'o')
+}
+
+/// ローマ字をひらがなにする
+/// 同じ入力には必ず同じ出力を返し、アルファベット以外は読み飛ばす
+pub fn romaji_to_hiragana(romaji: &str) -> String {
+    let chars: Vec<char> = romaji.to_ascii_lowercase().chars().filter(|c| c.is_ascii_alphabetic()).collect();
+    let mut hiragana = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        // n は母音か y が続かなければ「ん」, "nn" の後に母音が続けば 2 文字目の n は次の音にする ("onnot" -> おんのと)
+        if 'n' == c && !next.is_some_and(|next| is_vowel(next) || 'y' == next) {
+            hiragana.push('ん');
+            let after_nn = chars.get(i + 2).copied();
+            i += if Some('n') == next && !after_nn.is_some_and(|after| is_vowel(after) || 'y' == after) { 2 } else { 1 };
+            continue;
+        }
+
+        // 同じ子音の連続は促音 ("off" -> おっふ)
+        if !is_vowel(c) && Some(c) == next {
+            hiragana.push('っ');
+            i += 1;
+            continue;
+        }
+
+        let matched = (1..=3).rev()
+            .filter(|length| i + length <= chars.len())
+            .find_map(|length| {
+                let syllable: String = chars[i..i + length].iter().collect();
+                ROMAJI_TABLE.iter()
+                    .find(|(romaji, _)| *romaji == syllable)
+                    .map(|(_, kana)| (length, *kana))
+            });
+
+        match matched {
+            Some((length, kana)) => {
+                hiragana.push_str(kana);
+                i += length;
+            },
+            None => {
+                hiragana.push_str(leftover_consonant(c));
+                i += 1;
+            },
+        }
+    }
+
+    hiragana
+}
+
+/// ひらがな -> ヘボン式ローマ字 の表 (長い方から一致させる)
+const HEPBURN_TABLE: &[(&str, &str)] = &[
+    // 拗音など 2文字
+    ("きゃ", "kya"), ("きゅ", "kyu"), ("きょ", "kyo"),
+    ("ぎゃ", "gya"), ("ぎゅ", "gyu"), ("ぎょ", "gyo"),
+    ("しゃ", "sha"), ("しゅ", "shu"), ("しぇ", "she"), ("しょ", "sho"),
+    ("じゃ", "ja"), ("じゅ", "ju"), ("じぇ", "je"), ("じょ", "jo"),
+    ("ちゃ", "cha"), ("ちゅ", "chu"), ("ちぇ", "che"), ("ちょ", "cho"),
+    ("にゃ", "nya"), ("にゅ", "nyu"), ("にょ", "nyo"),
+    ("ひゃ", "hya"), ("ひゅ", "hyu"), ("ひょ", "hyo"),
+    ("びゃ", "bya"), ("びゅ", "byu"), ("びょ", "byo"),
+    ("ぴゃ", "pya"), ("ぴゅ", "pyu"), ("ぴょ", "pyo"),
+    ("みゃ", "mya"), ("みゅ", "myu"), ("みょ", "myo"),
+    ("りゃ", "rya"), ("りゅ", "ryu"), ("りょ", "ryo"),
+    ("ふぁ", "fa"), ("ふぃ", "fi"), ("ふぇ", "fe"), ("ふぉ", "fo"),
+    ("ゔぁ", "va"), ("ゔぃ", "vi"), ("ゔぇ", "ve"), ("ゔぉ", "vo"),
+    ("うぃ", "wi"), ("うぇ", "we"), ("いぇ", "ye"),
+    // 1文字
+    ("あ", "a"), ("い", "i"), ("う", "u"), ("え", "e"), ("お", "o"),
+    ("か", "ka"), ("き", "ki"), ("く", "ku"), ("け", "ke"), ("こ", "ko"),
+    ("が", "ga"), ("ぎ", "gi"), ("ぐ", "gu"), ("げ", "ge"), ("ご", "go"),
+    ("さ", "sa"), ("し", "shi"), ("す", "su"), ("せ", "se"), ("そ", "so"),
+    ("ざ", "za"), ("じ", "ji"), ("ず", "zu"), ("ぜ", "ze"), ("ぞ", "zo"),
+    ("た", "ta"), ("ち", "chi"), ("つ", "tsu"), ("て", "te"), ("と", "to"),
+    ("だ", "da"), ("ぢ", "ji"), ("づ", "zu"), ("で", "de"), ("ど", "do"),
+    ("な", "na"), ("に", "ni"), ("ぬ", "nu"), ("ね", "ne"), ("の", "no"),
+    ("は", "ha"), ("ひ", "hi"), ("ふ", "fu"), ("へ", "he"), ("ほ", "ho"),
+    ("ば", "ba"), ("び", "bi"), ("ぶ", "bu"), ("べ", "be"), ("ぼ", "bo"),
+    ("ぱ", "pa"), ("ぴ", "pi"), ("ぷ", "pu"), ("ぺ", "pe"), ("ぽ", "po"),
+    ("ま", "ma"), ("み", "mi"), ("む", "mu"), ("め", "me"), ("も", "mo"),
+    ("や", "ya"), ("ゆ", "yu"), ("よ", "yo"),
+    ("ら", "ra"), ("り", "ri"), ("る", "ru"), ("れ", "re"), ("ろ", "ro"),
+    ("わ", "wa"), ("を", "o"), ("ゔ", "vu"),
+];
+
+/// ひらがなをヘボン式のローマ字にする (長音の記号は付けない)
+/// 表に無い文字は読み飛ばす
+pub fn hiragana_to_hepburn(hiragana: &str) -> String {
+    let chars: Vec<char> = hiragana.chars().collect();
+    let mut romaji = String::new();
+    let mut i = 0;
+    let mut is_sokuon = false;
+
+    while i < chars.len() {
+        match chars[i] {
+            'っ' => {
+                is_sokuon = true;
+                i += 1;
+                continue;
+            },
+            'ん' => {
+                romaji.push('n');
+                // 母音や y が続く時は区切る ("ん" + "あ" -> "n'a")
+                if syllable_at(&chars[i + 1..]).is_some_and(|(_, next)| next.starts_with(|c| is_vowel(c) || 'y' == c)) {
+                    romaji.push('\'');
+                }
+                i += 1;
+                continue;
+            },
+            _ => (),
+        }
+
+        match syllable_at(&chars[i..]) {
+            Some((length, syllable)) => {
+                // 促音は次の子音を重ねる ("っち" は "tchi")
+                if is_sokuon {
+                    match syllable {
+                        _ if syllable.starts_with("ch") => romaji.push('t'),
+                        _ => romaji.extend(syllable.chars().next().filter(|c| !is_vowel(*c))),
+                    }
+                }
+                romaji.push_str(syllable);
+                i += length;
+            },
+            None => i += 1,
+        }
+        is_sokuon = false;
+    }
+
+    romaji
+}
+
+/// chars の先頭の音を (文字数, ローマ字) で返す
+fn syllable_at(chars: &[char]) -> Option<(usize, &'static str)> {
+    (1..=2).rev()
+        .filter(|length| *length <= chars.len())
+        .find_map(|length| {
+            let syllable: String = chars[..length].iter().collect();
+            HEPBURN_TABLE.iter()
+                .find(|(kana, _)| *kana == syllable)
+                .map(|(_, romaji)| (length, *romaji))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sen::{
+        Sen,
+        SenOp,
+    };
+
+    /// ゲーム中に onsen_name へ足される SenOp の名前 (OnsenName::from_sen_ops と同じく SenOp::to_string().to_lowercase())
+    fn op_roma() -> Vec<String> {
+        [SenOp::Off, SenOp::On, SenOp::Not, SenOp::Or, SenOp::And, SenOp::OrO(Sen::default()), SenOp::AndO(Sen::default())].iter()
+            .map(|sen_op| sen_op.to_string().to_lowercase())
+            .collect()
+    }
+
+    /// 手で確認した変換結果
+    const CORPUS: &[(&str, &str)] = &[
+        ("off", "おっふ"),
+        ("on", "おん"),
+        ("not", "のと"),
+        ("or", "おる"),
+        ("and", "あんど"),
+        ("oro", "おろ"),
+        ("ando", "あんど"),
+        ("onon", "おのん"),
+        ("onoff", "おのっふ"),
+        ("offon", "おっふぉん"),
+        ("offoff", "おっふぉっふ"),
+        ("onnot", "おんのと"),
+        ("ornot", "おるのと"),
+        ("onand", "おなんど"),
+        ("andand", "あんだんど"),
+        ("notnot", "のとのと"),
+        ("oronon", "おろのん"),
+        ("andoon", "あんどおん"),
+        ("oroando", "おろあんど"),
+        ("onornotand", "おのるのたんど"),
+    ];
+
+    /// 長さ depth 以下の SenOp の並びを全て作る
+    fn op_sequences(depth: usize) -> Vec<String> {
+        let op_roma = op_roma();
+        let mut sequences = vec!["".to_string()];
+        let mut all = vec![];
+        for _ in 0..depth {
+            sequences = sequences.iter()
+                .flat_map(|sequence| op_roma.iter().map(move |op| format!("{}{}", sequence, op)))
+                .collect();
+            all.extend(sequences.iter().cloned());
+        }
+
+        all
+    }
+
+    #[test]
+    fn corpus() {
+        for (roma, expected) in CORPUS {
+            assert_eq!(romaji_to_hiragana(roma), *expected, "roma: {}", roma);
+        }
+    }
+
+    #[test]
+    fn every_op_sequence_becomes_hiragana() {
+        for roma in op_sequences(4) {
+            let hiragana = romaji_to_hiragana(&roma);
+
+            assert!(!hiragana.is_empty(), "roma: {}", roma);
+            assert!(hiragana.chars().all(|c| ('\u{3041}'..='\u{309f}').contains(&c)), "roma: {} -> {}", roma, hiragana);
+        }
+    }
+
+    /// 三つ以上つなげても、前の SenOp の子音が次の母音とつながる
+    #[test]
+    fn long_op_sequences() {
+        assert_eq!(romaji_to_hiragana("notoffand"), "のとっふぁんど");
+        assert_eq!(romaji_to_hiragana("offnotoro"), "おっふのとろ");
+        assert_eq!(romaji_to_hiragana("ororor"), "おろろる");
+        assert_eq!(romaji_to_hiragana("andoroon"), "あんどろおん");
+        assert_eq!(romaji_to_hiragana("notonoffando"), "のとのっふぁんど");
+    }
+
+    #[test]
+    fn hiragana_to_hepburn_corpus() {
+        assert_eq!(hiragana_to_hepburn("おのるのたんど"), "onorunotando");
+        assert_eq!(hiragana_to_hepburn("おっふぉっふ"), "offoffu");
+        assert_eq!(hiragana_to_hepburn("おんのと"), "onnoto");
+        assert_eq!(hiragana_to_hepburn("あんどおん"), "andoon");
+        assert_eq!(hiragana_to_hepburn("おんあ"), "on'a");
+        assert_eq!(hiragana_to_hepburn("まっちゃ"), "matcha");
+        assert_eq!(hiragana_to_hepburn("いちばんゆ"), "ichiban'yu");
+
+        for roma in op_sequences(3) {
+            let hepburn = hiragana_to_hepburn(&romaji_to_hiragana(&roma));
+            assert!(hepburn.chars().all(|c| c.is_ascii_lowercase() || '\'' == c), "roma: {} -> {}", roma, hepburn);
+        }
+    }
+
+    #[test]
+    fn ignores_case_and_non_alphabetic() {
+        assert_eq!(romaji_to_hiragana("On Not!"), romaji_to_hiragana("onnot"));
+        assert_eq!(romaji_to_hiragana(""), "");
+    }
+}
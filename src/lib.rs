@@ -3,15 +3,36 @@
 use wasm_bindgen::prelude::*;
 use yew::prelude::*;
 
+mod audio;
+mod drag;
+mod integrity;
 mod js;
+mod kana;
 mod model;
+mod onsen_dictionary;
+mod rarity;
 mod sen;
+mod settings;
+mod solver;
+mod statistics;
+mod storage;
+#[cfg(feature = "sync")]
+pub mod sync;
+mod tutorial;
+mod unlock;
 
 /// index.html から呼ばれる最初の init wasm
 #[wasm_bindgen(start)]
 pub fn run_app() {
     set_panic_hook();
 
+    // IndexedDB は非同期でしか開けないので、開き終わってから表示する
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_futures::spawn_local(async {
+        storage::open_default_storage().await;
+        App::<model::MainModel>::new().mount_to_body();
+    });
+    #[cfg(not(target_arch = "wasm32"))]
     App::<model::MainModel>::new().mount_to_body();
 }
 
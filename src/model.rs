@@ -2,9 +2,14 @@ use serde::{
     Deserialize,
     Serialize,
 };
-use std::collections::HashMap;
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+    HashMap,
+};
 use yew::prelude::{
     html,
+    ChangeData,
     Component,
     ComponentLink,
     Html,
@@ -13,7 +18,21 @@ use yew::prelude::{
 use web_sys::*;
 
 use crate::sen;
+use crate::audio;
+use crate::drag;
+use crate::integrity;
 use crate::js;
+use crate::kana;
+use crate::onsen_dictionary;
+use crate::rarity;
+use crate::settings;
+use crate::solver;
+use crate::statistics;
+use crate::storage;
+#[cfg(feature = "sync")]
+use crate::sync;
+use crate::tutorial;
+use crate::unlock;
 
 /// イベントメッセージ
 #[derive(Clone)]
@@ -21,20 +40,34 @@ pub enum Message {
     // parent message
     ChangeToQuastionPage(String),
     ChangeToSelectPage,
+    ChangeToLockedStage(String),    // 開いていないステージを選んだ状態でステージ選択に戻る
+    ChangeToStatisticsPage,
+    ChangeToGalleryPage,
+    ChangeToSettingsPage,
+    StorageChanged(String, Option<String>),
+    #[cfg(feature = "sync")]
+    Synchronized,
+    KeyDown(String),
     None,
 
     // quastion page
-    TouchStart(web_sys::TouchEvent),
-    TouchMove(web_sys::TouchEvent),
-    TouchEnd,
-    TouchStartBackSen,
-    TouchStartFrontSen,
+    PointerDown(web_sys::PointerEvent),
+    PointerMove(web_sys::PointerEvent),
+    PointerUp(web_sys::PointerEvent),
+    PointerCancel,
+    PointerDownBackSen,
+    PointerDownFrontSen,
     BlinkAnimationEnd,
     OpenDoor,
     OpenedDoor,
+    StageRateOnsenName,     // クリアした後で、見つけられる温泉名を探して珍しさを付ける
+
+    // tutorial
+    TutorialDismiss,
 
     // select page
     StageSelect(i32),
+    SelectLevel(i32),
     StageBack,
     StageNext,
     StageEnter,
@@ -43,6 +76,29 @@ pub enum Message {
     StageHint,
     StageYoutube,
     AllComplete,
+
+    // statistics page
+    StatisticsClose,
+
+    // gallery page
+    GalleryClose,
+    GallerySearchReachableNames,    // まだ探していないステージを一つ探す
+
+    // settings page
+    SettingsMasterVolume(f64),
+    SettingsBgmVolume(f64),
+    SettingsSeVolume(f64),
+    SettingsMuteToggle,
+    SettingsPalette(settings::Palette),
+    SettingsPatternToggle,
+    SettingsLocale(String),
+    SettingsRomanizedToggle,
+    SettingsEnglishToggle,
+    SettingsReducedMotionToggle,
+    SettingsResetTutorial,
+    SettingsResetProgress,
+    SettingsClearCache,
+    SettingsClose,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -51,87 +107,106 @@ struct OnsenName {
 
     #[serde(default)]
     roma: String,
+    #[serde(default)]
+    ops: Vec<String>,   // roma の元になった SenOp の名前の並び
+    #[serde(default)]
+    famous: Option<onsen_dictionary::FamousOnsen>,
+    #[serde(default)]
+    rarity: Option<u32>,    // 珍しさ (0 ~ 100)
 }
 impl OnsenName {
     /// roma をひらがなに変換して name に入れる
+    /// ops が辞書に載っていれば名のある温泉にする
     fn japanification(&mut self) {
-        // char += 一文字とる、if char == "" :char_index = i
-        // romaji::convert する、!= だと、name に push、char を空にする
-        // char が 5 文字になる or 残っている状態で最後までいくと、char_index+1 まで戻る、char_index は未処理文字とする
-        let mut iter = self.roma.chars();
-        let mut string = "".to_string();
-        let mut char_index: usize = 0;
-        let mut i = 0;
-        self.name = "".to_string();
-        loop {
-            let mut char_opt = iter.next();
-            if 4 <= string.len() {
-                js::console_log!("skipped unconverted roma [{}].", string);
-                i = char_index + 1;
-                iter = self.roma.chars();
-                char_opt = iter.nth(i);
-
-                self.name.push_str(&romaji::convert::katakana_to_hiragana(
-                    Self::special_katakata_map( &string.chars().nth(0).unwrap().to_string() )
-                ));
-                string.clear();
-            } else if char_opt == None {
-                if !string.is_empty() {
-                    js::console_log!("ended unconverted roma [{}].", string);
-                    self.name.push_str( &romaji::convert::katakana_to_hiragana(Self::special_katakata_map(&string)) );
-                }
-                break;
-            }
+        self.name = kana::romaji_to_hiragana(&self.roma);
+        self.famous = Resource::onsen_dictionary().lookup(&self.ops).cloned();
+    }
 
-            if string.is_empty() {
-                char_index = i;
-            }
-            string.push( char_opt.unwrap() );
+    /// SenOp の並びから温泉名を作る
+    fn from_sen_ops(sen_ops: &[sen::SenOp]) -> Self {
+        let mut onsen_name = Self::default();
+        for sen_op in sen_ops {
+            onsen_name += sen_op.to_string().to_lowercase();
+        }
+        onsen_name.japanification();
 
-            js::console_log!("i: {}, char: {:?}, string: {}, name: {}", i, char_opt, string, self.name);
-            if string == "n" {
-                i += 1;
-                continue;
-            }
+        onsen_name
+    }
 
-            let mut katakanas = romaji::convert::romaji_to_katakana(string.clone());
-            if katakanas != string {
-                // 促音だけが変換される場合があるので確認する
-                // "nn" で促音が入る場合があるので消す
-                if "nn" == string {
-                    katakanas = "ン".to_string();
-                }
-                let mut hiragana = "".to_string();
-                for katakana in katakanas.chars() {
-                    if katakana.is_ascii_alphabetic() {
-                        hiragana.clear();
-                        break;
-                    }
-                    hiragana.push_str( &romaji::convert::katakana_to_hiragana(katakana.to_string()) );
-                }
+    /// 同じステージで見つけられる温泉名の中での珍しさを付ける
+    fn rate(&mut self, reachable_names: &ReachableNames) {
+        let producer_count = reachable_names.counts.get(&self.to_string()).copied().unwrap_or(0);
+        // 探索しきれていない、探した手数より長いなら、見つからないのは探していないだけかもしれないので珍しさは分からない
+        let is_known = 0 < producer_count || (reachable_names.is_complete && self.ops.len() <= reachable_names.max_depth);
+        self.rarity = if is_known {
+            Some(rarity::score(&self.name, producer_count, reachable_names.total))
+        } else {
+            None
+        };
+    }
+}
+impl OnsenName {
+    /// ヘボン式ローマ字での温泉名 ("Onorunotando-sen", 名のある温泉は読みから)
+    fn romanized(&self) -> String {
+        let romaji = match &self.famous {
+            Some(famous) => kana::hiragana_to_hepburn(&famous.reading),
+            None => format!("{}-sen", kana::hiragana_to_hepburn(&self.name)),
+        };
 
-                if !hiragana.is_empty() {
-                    // 促音もASCIIも含まれていなかった
-                    self.name.push_str(&hiragana);
-                    string.clear();
-                }
-            }
-            i += 1;
+        let mut chars = romaji.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => romaji,
         }
+    }
+
+    /// 英語での温泉名
+    fn english(&self) -> String {
+        match &self.famous {
+            Some(famous) if !famous.english.is_empty() => famous.english.clone(),
+            _ => onsen_dictionary::english_gloss(&self.ops),
+        }
+    }
 
-        js::console_log!("onsen name is [{}]", self.name);
+    /// 温泉名の下に出す、読みや言語設定に応じた別表記の HTML を返す
+    fn get_sub_name_html(&self) -> Html {
+        // まだ名前が決まっていない
+        if self.ops.is_empty() {
+            return html! {};
+        }
+
+        let name_forms = Resource::user_storage().settings.name_forms();
+        let famous = self.famous.as_ref();
+
+        html! {
+            <div id="sub_name_div">
+                { for famous.map(|famous| html! { <div id="famous_onsen_reading">{ &famous.reading }</div> }) }
+                { if name_forms.romanized { html! { <div id="stage_name_romanized">{ self.romanized() }</div> } } else { html! {} } }
+                { if name_forms.english { html! { <div id="stage_name_english">{ self.english() }</div> } } else { html! {} } }
+                { for famous.map(|famous| html! { <div id="famous_onsen_flavour">{ &famous.flavour }</div> }) }
+            </div>
+        }
     }
 
-    /// roma を適当につなげただけだと余る文字が出てくるので、特別にカタカナへ変換する (元の英語の発音に基づきかつ子音)
-    fn special_katakata_map(romaji: &str) -> String {
-        match romaji {
-            "d" => "ド",    // an 'd'
-            "f" => "フ",    // o 'f' f
-            "n" => "ン",    // a 'n' d
-            "r" => "ル",    // o 'r'
-            "t" => "ト",    // no 't'
-            _ => "",
-        }.to_string()
+    /// 珍しさのバッジの HTML を返す
+    fn get_rarity_html(&self) -> Html {
+        let score = match self.rarity {
+            Some(score) => score,
+            None => return html! {},
+        };
+        let tier = rarity::RarityTier::from_score(score);
+        let words = rarity::find_words(&self.name);
+        let title = if words.is_empty() {
+            format!("珍しさ {}", score)
+        } else {
+            format!("珍しさ {} ({})", score, words.join("・"))
+        };
+
+        html! {
+            <div id="stage_name_rarity" class=tier.class_name() title=title>
+                { tier.label() }
+            </div>
+        }
     }
 }
 impl Default for OnsenName {
@@ -139,21 +214,41 @@ impl Default for OnsenName {
         Self {
             name: "？？？".to_string(),
             roma: "".to_string(),
+            ops: vec![],
+            famous: None,
+            rarity: None,
         }
     }
 }
 impl std::fmt::Display for OnsenName {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}泉", self.name)
+        match &self.famous {
+            Some(famous) => write!(f, "{}", famous.kanji),
+            None => write!(f, "{}泉", self.name),
+        }
     }
 }
 impl std::ops::AddAssign<String> for OnsenName {
     fn add_assign(&mut self, rhs: String) {
         self.roma += &rhs;
+        self.ops.push(rhs);
     }
 }
 
 
+/// ステージで見つけられる温泉名
+struct ReachableNames {
+    counts: BTreeMap<String, usize>,    // 温泉名毎の、その名前になる SenOp の並びの数
+    total: usize,                       // クリアできる SenOp の並びの数
+    is_complete: bool,                  // 探索しきれたか
+    max_depth: usize,                   // 探した手数
+}
+impl ReachableNames {
+    // ops_border が大きいステージでも探索が終わるように、これより深くは探さない
+    const SEARCH_DEPTH_LIMIT: usize = 6;
+}
+
+
 // 問題
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 struct OnsenStatus {
@@ -172,6 +267,14 @@ struct OnsenStatus {
     info: String,
     onsen_name: OnsenName,
 
+    // このステージの BGM (sound_manifest.json の名前、無ければ sound_manifest.json で決める)
+    #[serde(default)]
+    bgm: Option<String>,
+
+    // このステージを開く条件 (quastions.json にだけ書くので、セーブデータには書き出さない)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    unlock: Vec<unlock::UnlockRule>,
+
     key: String,
     back_key: String,
     next_key: String,
@@ -205,6 +308,39 @@ impl OnsenStatus {
         self.is_clear && self.is_lower_border && self.is_using_onsen
     }
 
+    /// 獲得した☆の数
+    fn star_count(&self) -> i32 {
+        self.is_clear as i32 + self.is_using_onsen as i32 + self.is_lower_border as i32
+    }
+
+    /// 別タブで保存された状態と合わせる
+    /// ☆とヒントはどちらかで獲得していれば獲得済みにし、温泉名などの結果は良い方をとる
+    /// 良さは ☆の数 > 操作回数の少なさ > roma の辞書順 > ヒントを使っていない方 で比べる
+    /// それでも並ぶ時は書き出した JSON で比べるので、どちらのタブから合わせても同じ結果になる
+    fn merge(&mut self, other: &Self) {
+        let rank = |status: &Self| (
+            status.star_count(),
+            -status.ops_count,
+            status.onsen_name.roma.clone(),
+            !status.use_hint,
+            serde_json::to_string(status).unwrap_or_default(),
+        );
+        let (is_clear, is_using_onsen, is_lower_border, use_hint) = (
+            self.is_clear || other.is_clear,
+            self.is_using_onsen || other.is_using_onsen,
+            self.is_lower_border || other.is_lower_border,
+            self.use_hint || other.use_hint,
+        );
+
+        if rank(self) < rank(other) {
+            *self = other.clone();
+        }
+        self.is_clear = is_clear;
+        self.is_using_onsen = is_using_onsen;
+        self.is_lower_border = is_lower_border;
+        self.use_hint = use_hint;
+    }
+
     /// 温度の設定
     fn set_temperature(mut self, temperature: i32) -> Self {
         self.temperature = temperature;
@@ -225,6 +361,9 @@ impl OnsenStatus {
             self.init();
             self.sen.init();
         }
+        if !self.use_hint && self.key.starts_with("stage_") {
+            Resource::user_storage().statistics(&self.key).record_hint();
+        }
         self.use_hint = true;
 
         Resource::user_storage().onsen_status.set_onsen_data(
@@ -259,14 +398,52 @@ impl OnsenStatus {
         Self::get_onsen_status_from_name(&Resource::user_storage().init_onsen_key)
     }
 
+    /// "stage_{level}_{number}" のステージキーから level を返す、ステージでなければ None
+    fn stage_level(&self) -> Option<i32> {
+        self.key.strip_prefix("stage_")?.split('_').next()?.parse().ok()
+    }
+
+    /// ステージを開くのに足りないものの説明を返す、開いていれば空
+    /// 条件はレベルの条件と quastions.json のステージの条件を合わせたもの
+    fn missing_requirements(&self) -> Vec<String> {
+        let manager = Resource::onsen_status_manager();
+        let mut rules = self.stage_level().map(|stage_level| manager.level_unlock_rules(stage_level)).unwrap_or_default();
+        if let Some(status) = manager.onsen_status_list.get(&self.key) {
+            rules.extend(status.unlock.iter().cloned());
+        }
+
+        unlock::missing_requirements(&rules, Resource::user_storage())
+    }
+
     /// stage 番号から OnsenStatus を返す
     fn get_onsen_status<'a>(stage_level: i32, stage_number: i32) -> &'a Self {
         Self::get_onsen_status_from_name( &Self::get_onsen_key_from_stage(stage_level, stage_number) )
     }
 
+    /// ops_border 手以内でクリアした時に付く温泉名を全て探す
+    fn search_reachable_names(&self) -> ReachableNames {
+        let mut sen = sen::SenManager::default();
+        sen.deep_copy(&self.sen);
+        let max_depth = (self.ops_border.max(0) as usize).min(ReachableNames::SEARCH_DEPTH_LIMIT);
+
+        let solution = solver::Solver::clearing_sequences(&sen, &QuastionPage::INITIAL_SEN_OP_LIST, self.temperature, max_depth);
+        let mut counts = BTreeMap::new();
+        for sen_ops in &solution.sequences {
+            *counts.entry(OnsenName::from_sen_ops(sen_ops).to_string()).or_insert(0) += 1;
+        }
+
+        ReachableNames {
+            counts,
+            total: solution.sequences.len(),
+            is_complete: solution.is_complete,
+            max_depth,
+        }
+    }
+
     /// ポップアップのための HTML を返す
     fn get_popup_html(&self, link: &ComponentLink<MainModel>, is_stage_view: bool) -> Html {
-        let tweet_url = if self.is_cleared() {
+        // 改ざんされたセーブデータではクリアしたことを共有しない
+        let tweet_url = if self.is_cleared() && !Resource::user_storage().is_modified {
             format!("{}に入ったよ！", &self.onsen_name)
         } else {
             format!("{}に入りたい！", &self.info)
@@ -294,14 +471,16 @@ impl OnsenStatus {
                     <div id="stage_name">
                         { &self.onsen_name }
                     </div>
+                    { self.onsen_name.get_rarity_html() }
                 </div>
+                { self.onsen_name.get_sub_name_html() }
                 <div id="stage_detail">
                     { if self.is_clear {"★"} else {"☆"} }{ if self.is_using_onsen {"★"} else {"☆"} }{ if self.is_lower_border {"★"} else {"☆"} }
                 </div>
                 { if is_stage_view { self.get_move_navigation_html(link) } else { self.get_clear_navigation_html(link) } }
                 <div id="stage_external_icon">
                     <button class="tweet_icon"
-                        ontouchend=link.callback(|_| Message::StageTweet)
+                        onclick=link.callback(|_| Message::StageTweet)
                     >
                         <a target="_blank" href={ tweet_url }>
                             <img src="/resource/image/Twitter_social_icons_rounded_square_blue.png" />
@@ -309,7 +488,7 @@ impl OnsenStatus {
                     </button>
                     { hint_button }
                     <button class="youtube_icon"
-                        ontouchend=link.callback(|_| Message::StageYoutube)
+                        onclick=link.callback(|_| Message::StageYoutube)
                     >
                         <a target="_blank" href="https://www.youtube.com/channel/UCVxzptdb4sw84z2rTh_1AJQ?sub_confirmation=1">
                             <img src="/resource/image/youtube_social_squircle_red.png" />
@@ -327,12 +506,12 @@ impl OnsenStatus {
         if key == self.key {
             // 無表示だとレイアウトが崩れるので、空ボタンを返す
             return html!{
-                <button type="button" ontouchend=link.callback(move |event| message.clone())>{ "　　" }</button>
+                <button type="button" onclick=link.callback(move |event| message.clone())>{ "　　" }</button>
             };
         }
 
         html! {
-            <button type="button" ontouchend=link.callback(move |event| message.clone())>
+            <button type="button" onclick=link.callback(move |event| message.clone())>
                 { text }
             </button>
         }
@@ -343,7 +522,7 @@ impl OnsenStatus {
         html! {
             <div id="stage_navigation">
                 { self.get_navigation_button(link, self.back_key(), "前", Message::StageBack) }
-                <button type="button" ontouchend=link.callback(|event| Message::StageEnter)>
+                <button type="button" onclick=link.callback(|event| Message::StageEnter)>
                     { "入" }
                 </button>
                 { self.get_navigation_button(link, self.next_key(), "次", Message::StageNext) }
@@ -355,10 +534,10 @@ impl OnsenStatus {
     fn get_clear_navigation_html(&self, link: &ComponentLink<MainModel>) -> Html {
         html! {
             <div id="stage_navigation">
-                <button type="button" ontouchend=link.callback(|event| Message::StageBack)>
+                <button type="button" onclick=link.callback(|event| Message::StageBack)>
                     { "戻" }
                 </button>
-                <button type="button" ontouchend=link.callback(|event| Message::StageEnter)>
+                <button type="button" onclick=link.callback(|event| Message::StageEnter)>
                     { "再" }
                 </button>
                 { self.get_navigation_button(link, self.next_key(), "次", Message::StageNext) }
@@ -408,10 +587,10 @@ impl OnsenStatus {
                 // 存在すれば上書き、なければ作成
                 match js::dom::get_element_by_id::<HtmlImageElement>( &format!("hint_bit_{}", index) ) {
                     Some(sen_image) => {
-                        sen_image.set_src( &sen.to_file_name(index) );
+                        sen_image.set_src( &Resource::mark_path(&sen.to_file_name(index)) );
                     },
                     None => {
-                        let sen_image = js::dom::make_img_element( &sen.to_file_name(index) );
+                        let sen_image = js::dom::make_img_element( &Resource::mark_path(&sen.to_file_name(index)) );
                         sen_image.set_id( &format!("hint_bit_{}", index) );
                         sen_image.set_class_name("hint_sen");
 
@@ -428,6 +607,14 @@ impl OnsenStatus {
 struct OnsenStatusManager {
     pub level_list: Vec<i32>,
     pub onsen_status_list: HashMap<String, OnsenStatus>,
+
+    // レベル毎のステージ選択の BGM (sound_manifest.json の名前、無ければ sound_manifest.json で決める)
+    #[serde(default)]
+    pub level_bgm: HashMap<String, String>,
+
+    // レベル毎の開く条件 (無ければ最初から開いている、前のレベルをクリアさせるなら clear_level を書く)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub level_unlock: HashMap<String, Vec<unlock::UnlockRule>>,
 }
 impl OnsenStatusManager {
     const FILE_PATH: &'static str = "/data/quastions.json";
@@ -449,6 +636,46 @@ impl OnsenStatusManager {
     fn set_onsen_data(&mut self, key: &str, onsen_data: &OnsenStatus) {
         *self.onsen_status_list.get_mut(key).unwrap() = onsen_data.clone();
     }
+
+    /// レベルを開く条件を返す、quastions.json に書かれていなければ条件は無い
+    fn level_unlock_rules(&self, stage_level: i32) -> Vec<unlock::UnlockRule> {
+        self.level_unlock.get(&stage_level.to_string()).cloned().unwrap_or_default()
+    }
+
+    /// レベルにあるステージの番号を並べて返す
+    fn stage_numbers(&self, stage_level: i32) -> Vec<i32> {
+        let prefix = format!("stage_{}_", stage_level);
+        let mut stage_numbers: Vec<i32> = self.onsen_status_list.keys()
+            .filter_map(|key| key.strip_prefix(&prefix)?.parse().ok())
+            .collect();
+        stage_numbers.sort_unstable();
+
+        stage_numbers
+    }
+
+    /// "stage_{level}_{number}" のステージキーを level, number 順に並べて返す
+    fn stage_keys(&self) -> Vec<&String> {
+        let sort_key = |key: &String| {
+            let mut numbers = key.split('_').skip(1).map(|number| number.parse::<i32>().unwrap_or(-1));
+            (numbers.next().unwrap_or(-1), numbers.next().unwrap_or(-1), key.clone())
+        };
+
+        let mut keys: Vec<&String> = self.onsen_status_list.keys()
+            .filter(|key| key.starts_with("stage_"))
+            .collect();
+        keys.sort_by_key(|key| sort_key(key));
+
+        keys
+    }
+}
+
+// 図鑑に載る、見つけた温泉名
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct DiscoveredName {
+    stage: String,
+    name: String,
+    ops: Vec<String>,
+    discovered_at: f64,
 }
 
 // セーブデータを管理するクラス
@@ -457,9 +684,33 @@ struct UserStorage {
     init_onsen_key: String,
     init_stage_level: i32,
     onsen_status: OnsenStatusManager,
+
+    #[serde(default)]
+    statistics: BTreeMap<String, statistics::StageStatistics>,
+
+    // 署名が一致しないセーブデータを読み込んだことがあるか (一度立つと消えない)
+    #[serde(default)]
+    is_modified: bool,
+
+    // 見つけた温泉名 (図鑑)
+    #[serde(default)]
+    discovered_names: Vec<DiscoveredName>,
+
+    #[serde(default)]
+    settings: settings::Settings,
+
+    // 終えたチュートリアル (None は記録する前のセーブデータ)
+    #[serde(default)]
+    completed_tutorials: Option<BTreeSet<String>>,
+
+    // 進行状況を消した回数 (別タブや同期先の進行状況が消す前のものかを見分ける)
+    #[serde(default)]
+    reset_epoch: u32,
 }
 impl UserStorage {
     const STORAGE_DATA_NAME: &'static str = "save_data.json";
+    // 署名付きで保存したことがあるかの目印 (これがあるのに署名が無いセーブデータは書き換えられたものとみなす)
+    const SEALED_MARKER_NAME: &'static str = "save_data.sealed";
 
     // UserStorage を作成するために初期値用
     fn new(init_onsen_key: String) -> Self {
@@ -467,27 +718,301 @@ impl UserStorage {
             init_onsen_key,
             init_stage_level: 0,
             onsen_status: OnsenStatusManager::from_file(),
+            statistics: BTreeMap::new(),
+            is_modified: false,
+            discovered_names: vec![],
+            settings: settings::Settings::default(),
+            completed_tutorials: Some(BTreeSet::new()),
+            reset_epoch: 0,
+        }
+    }
+
+    /// チュートリアルを終えているか
+    fn is_tutorial_completed(&self, name: &str) -> bool {
+        self.completed_tutorials.as_ref().is_some_and(|completed| completed.contains(name))
+    }
+
+    /// チュートリアルを終えたことを記録する
+    fn complete_tutorial(&mut self, name: &str) {
+        self.completed_tutorials.get_or_insert_with(BTreeSet::new).insert(name.to_string());
+    }
+
+    /// チュートリアルをもう一度表示するようにする
+    fn reset_tutorials(&mut self) {
+        self.completed_tutorials = Some(BTreeSet::new());
+    }
+
+    /// 進行状況 (クリア情報、統計、図鑑) を最初の状態に戻す
+    /// 設定と、書き換えられたことがあるかの目印は残す
+    /// 別タブや同期先に残っている進行状況で戻らないように、reset_epoch を進めて消す前のものと見分ける
+    fn reset_progress(&mut self) {
+        let settings = self.settings.clone();
+        let is_modified = self.is_modified;
+        let reset_epoch = self.reset_epoch;
+
+        *self = Self::new("top".to_string());
+        self.settings = settings;
+        self.is_modified = is_modified;
+        self.reset_epoch = reset_epoch + 1;
+    }
+
+    /// ステージで見つけた温泉名を図鑑に加える、既に見つけていれば何もしない
+    fn discover_name(&mut self, key: &str, onsen_name: &OnsenName) {
+        let name = onsen_name.to_string();
+        if self.discovered_names.iter().any(|discovered| discovered.stage == key && discovered.name == name) {
+            return;
+        }
+
+        js::console_log!("discovered onsen name [{}] at {}", name, key);
+        self.discovered_names.push(DiscoveredName {
+            stage: key.to_string(),
+            name,
+            ops: onsen_name.ops.clone(),
+            discovered_at: js::time::now().as_f64().unwrap(),
+        });
+    }
+
+    /// 別タブで保存された進行状況と合わせる
+    /// init_onsen_key などの画面の状態はタブ毎のものなので、自分の方を残す
+    /// 進行状況を消した回数が違うなら合わせずに、後から消した方の進行状況にする
+    fn merge(&mut self, other: &Self) {
+        self.is_modified |= other.is_modified;
+        match self.reset_epoch.cmp(&other.reset_epoch) {
+            // 消す前の進行状況なので合わせない
+            std::cmp::Ordering::Greater => return,
+            std::cmp::Ordering::Less => {
+                self.adopt_progress(other);
+                return;
+            },
+            std::cmp::Ordering::Equal => (),
+        }
+
+        for (key, other_status) in &other.onsen_status.onsen_status_list {
+            // ステージが増減すると参照している &'static OnsenStatus が無効になるので、既存のステージのみ合わせる
+            if let Some(status) = self.onsen_status.onsen_status_list.get_mut(key) {
+                status.merge(other_status);
+            }
+        }
+        for (key, other_statistics) in &other.statistics {
+            self.statistics(key).merge(other_statistics);
+        }
+        if let Some(other_completed) = &other.completed_tutorials {
+            self.completed_tutorials.get_or_insert_with(BTreeSet::new).extend(other_completed.iter().cloned());
+        }
+
+        // 図鑑は両方を足して、同じ温泉名は先に見つけた方を残す
+        self.discovered_names.extend(other.discovered_names.iter().cloned());
+        self.discovered_names.sort_by(|a, b| {
+            (&a.stage, &a.name).cmp(&(&b.stage, &b.name))
+                .then(a.discovered_at.partial_cmp(&b.discovered_at).unwrap_or(std::cmp::Ordering::Equal))
+                .then(a.ops.cmp(&b.ops))
+        });
+        self.discovered_names.dedup_by(|later, earlier| later.stage == earlier.stage && later.name == earlier.name);
+        self.discovered_names.sort_by(|a, b| a.discovered_at.partial_cmp(&b.discovered_at).unwrap_or(std::cmp::Ordering::Equal));
+    }
+
+    /// 別タブで進行状況が消されていたので、その後の進行状況に置き換える
+    fn adopt_progress(&mut self, other: &Self) {
+        for (key, status) in self.onsen_status.onsen_status_list.iter_mut() {
+            if let Some(other_status) = other.onsen_status.onsen_status_list.get(key) {
+                *status = other_status.clone();
+            }
+        }
+        self.statistics = other.statistics.clone();
+        self.discovered_names = other.discovered_names.clone();
+        self.completed_tutorials = other.completed_tutorials.clone();
+        self.reset_epoch = other.reset_epoch;
+    }
+
+    /// 別タブで保存データが書き換えられた時に呼ぶ
+    /// 合わせた結果が書き換えられた内容と違う (こちらの方が良い記録がある) なら保存し直して、別タブにも反映させる
+    fn on_storage_changed(&mut self, new_value: &str) {
+        let (payload, verification) = integrity::open(new_value);
+        if integrity::Verification::Verified != verification {
+            js::console_log!("ignored unverified {} from other tab ({:?})", Self::STORAGE_DATA_NAME, verification);
+            return;
+        }
+
+        let other = match serde_json::from_str::<Self>(&payload) {
+            Ok(other) => other,
+            Err(e) => {
+                js::console_log!("ignored invalid {} from other tab E:{:?}", Self::STORAGE_DATA_NAME, e);
+                return;
+            },
+        };
+
+        self.merge(&other);
+        // 設定は進行状況ではないので、後から書き換えられた方に合わせる
+        self.settings = other.settings.clone();
+        Resource::sound().apply_settings(&self.settings.audio);
+
+        // HashMap は書き出す順番が一定でないので、文字列ではなく Value で比べる
+        let mut merged = other;
+        merged.merge(self);
+        if serde_json::to_value(&merged).ok() != serde_json::from_str::<serde_json::Value>(&payload).ok() {
+            self.save_data();
         }
     }
 
+    /// ステージの統計を返す、無ければ作成
+    fn statistics(&mut self, key: &str) -> &mut statistics::StageStatistics {
+        self.statistics.entry(key.to_string()).or_default()
+    }
+
     // UserStorage へ保存
     fn save_data(&self) {
-        js::fs::write_storage(
-            Self::STORAGE_DATA_NAME.to_string(),
-            serde_json::to_string( &self ).unwrap(),
+        if !Resource::storage().write(
+            Self::STORAGE_DATA_NAME,
+            &integrity::seal( &serde_json::to_string( &self ).unwrap() ),
+        ) {
+            js::console_log!("failed to write {}", Self::STORAGE_DATA_NAME);
+            return;
+        }
+        Resource::storage().write(Self::SEALED_MARKER_NAME, "1");
+    }
+
+    /// 保存されていた文字列から読み込む
+    /// 署名が一致しなければ is_modified を立てる、署名が無いのは署名を付ける前の古いセーブデータなので期限までは一度だけ信用する
+    fn from_saved_data(data: &str) -> Result<Self, serde_json::Error> {
+        let (payload, verification) = integrity::open(data);
+        let mut user_storage = serde_json::from_str::<Self>(&payload)?;
+
+        let is_modified = integrity::is_modified(
+            verification,
+            Resource::storage().read(Self::SEALED_MARKER_NAME).is_some(),
+            js::time::now().as_f64().unwrap(),
         );
+        if is_modified && !user_storage.is_modified {
+            js::console_log!("{} is modified. it will not be shared.", Self::STORAGE_DATA_NAME);
+            user_storage.is_modified = true;
+        }
+
+        // チュートリアルを記録する前のセーブデータは、最初のステージをクリアしていれば終えたものとする
+        if user_storage.completed_tutorials.is_none() {
+            let mut completed = BTreeSet::new();
+            if user_storage.onsen_status.onsen_status_list.get("top").is_some_and(|status| status.is_cleared()) {
+                completed.insert("top".to_string());
+            }
+            user_storage.completed_tutorials = Some(completed);
+        }
+
+        Ok(user_storage)
+    }
+}
+impl unlock::Progress for UserStorage {
+    fn is_stage_cleared(&self, key: &str) -> bool {
+        self.onsen_status.onsen_status_list.get(key).is_some_and(|status| status.is_cleared())
+    }
+
+    fn level_stage_keys(&self, level: i32) -> Vec<String> {
+        Resource::onsen_status_manager().stage_numbers(level).iter()
+            .map(|&stage_number| OnsenStatus::get_onsen_key_from_stage(level, stage_number))
+            .collect()
+    }
+
+    fn star_count(&self, level: Option<i32>) -> i32 {
+        self.onsen_status.onsen_status_list.values()
+            .filter(|status| status.key.starts_with("stage_") && level.is_none_or(|level| status.stage_level() == Some(level)))
+            .map(|status| status.star_count())
+            .sum()
+    }
+
+    fn discovered_name_count(&self) -> usize {
+        self.discovered_names.len()
+    }
+
+    fn stage_label(&self, key: &str) -> String {
+        match Resource::onsen_status_manager().onsen_status_list.get(key) {
+            Some(status) => format!("「{}」", status.info),
+            None => key.to_string(),
+        }
+    }
+}
+/// 進行状況の同期先の設定
+#[cfg(feature = "sync")]
+#[derive(Debug, Deserialize, Serialize)]
+struct SyncConfig {
+    endpoint: String,
+    user_id: String,
+}
+#[cfg(feature = "sync")]
+impl SyncConfig {
+    const STORAGE_DATA_NAME: &'static str = "sync_config.json";
+    const ENDPOINT_PARAM_NAME: &'static str = "sync_endpoint";
+    const USER_ID_PARAM_NAME: &'static str = "sync_id";
+
+    /// GETパラメータで指定されていれば保存して返す、なければ前回保存したものを返す
+    fn load() -> Option<Self> {
+        if let (Some(endpoint), Some(user_id)) = (js::dom::get_param(Self::ENDPOINT_PARAM_NAME), js::dom::get_param(Self::USER_ID_PARAM_NAME)) {
+            let config = Self {
+                endpoint,
+                user_id,
+            };
+            Resource::storage().write(Self::STORAGE_DATA_NAME, &serde_json::to_string(&config).unwrap());
+
+            return Some(config);
+        }
+
+        serde_json::from_str(&Resource::storage().read(Self::STORAGE_DATA_NAME)?).ok()
+    }
+}
+#[cfg(feature = "sync")]
+impl UserStorage {
+    /// 同期先と進行状況を合わせる、同期先が設定されていなければ何もしない
+    /// 応答を待たずに戻り、合わせ終えたら synchronized を呼ぶ
+    fn synchronize(&self, synchronized: yew::Callback<()>) {
+        let config = match SyncConfig::load() {
+            Some(config) => config,
+            None => return,
+        };
+        if self.is_modified {
+            js::console_log!("modified progress is not synchronized.");
+            return;
+        }
+
+        wasm_bindgen_futures::spawn_local(async move {
+            let mut backend = sync::HttpSyncBackend::new(&config.endpoint, &config.user_id);
+
+            // 応答を待つ間に進んだ分も含めるように、合わせる時に改めて取り出す
+            let result = sync::synchronize(&mut backend, |remote| {
+                let user_storage = Resource::user_storage();
+                // 進行状況を消す前の同期先は merge で合わせずに上書きされる
+                // 統計の回数は大きい方をとるので、前の同期から両方の端末で数えた分は足されない (StageStatistics::merge)
+                if let Some(remote) = remote {
+                    match integrity::open(remote) {
+                        (payload, integrity::Verification::Verified) => match serde_json::from_str::<Self>(&payload) {
+                            Ok(remote) => user_storage.merge(&remote),
+                            Err(e) => js::console_log!("ignored invalid remote progress E:{:?}", e),
+                        },
+                        (_, verification) => js::console_log!("ignored unverified remote progress ({:?})", verification),
+                    }
+                }
+                integrity::seal(&serde_json::to_string(user_storage).unwrap())
+            }).await;
+
+            match result {
+                Ok(_) => {
+                    js::console_log!("synchronized progress with {}", config.endpoint);
+                    Resource::user_storage().save_data();
+                    synchronized.emit(());
+                },
+                Err(e) => js::console_log!("failed to synchronize progress E:{:?}", e),
+            }
+        });
     }
 }
+
 impl Default for UserStorage {
     // UserStorage から読み込み。無いなら作成
     fn default() -> Self {
-        let file = js::fs::read_storage( Self::STORAGE_DATA_NAME.to_string() );
+        let data = Resource::storage().read( Self::STORAGE_DATA_NAME );
 
-        match file {
-            Some(file) => {
-                let reader = std::io::BufReader::new(file);
-                match serde_json::from_reader::<_, Self>(reader) {
+        match data {
+            Some(data) => {
+                match Self::from_saved_data(&data) {
                     Ok(data) => {
+                        // 署名を付け直す (古いセーブデータの移行と、is_modified の保存)
+                        data.save_data();
                         data
                     },
                     Err(e) => {
@@ -505,8 +1030,8 @@ impl Default for UserStorage {
     }
 }
 
-use strum::IntoEnumIterator;
-#[derive(Clone, Copy, Debug, strum_macros::EnumIter, PartialEq)]
+/// プログラムから鳴らす効果音 (音源は SoundManifest で決める)
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum SoundKind {
     ButtonUp,
     DownTemp,
@@ -515,100 +1040,158 @@ enum SoundKind {
     Sen11,
     StageEnter,
     UpTemp,
-    BgmStageDefault,
-    BgmStageTop,
-    BgmStageLevel1,
-    BgmStageLevel2,
 }
 impl SoundKind {
+    /// SoundManifest での名前
     fn get_id(&self) -> &str {
         match *self {
-            Self::ButtonUp => "se_button_up.mp3",
-            Self::DownTemp => "se_down_temp.mp3",
-            Self::Sen01 => "se_sen_0b01.wav",
-            Self::Sen10 => "se_sen_0b10.wav",
-            Self::Sen11 => "se_sen_0b11.wav",
-            Self::StageEnter => "se_stage_enter.mp3",
-            Self::UpTemp => "se_up_temp.ogg",
-            Self::BgmStageDefault => "bgm_stage_default.wav",
-            Self::BgmStageTop => "bgm_stage_top.wav",
-            Self::BgmStageLevel1 => "bgm_stage_level_1.wav",
-            Self::BgmStageLevel2 => "bgm_stage_level_2.wav",
-        }
-    }
-
-    fn get_audio_html(&self) -> Html {
-        html! {
-            <audio id={ self.get_id() } preload="auto" src={ format!("/resource/sound/{}", self.get_id()) } />
+            Self::ButtonUp => "se_button_up",
+            Self::DownTemp => "se_down_temp",
+            Self::Sen01 => "se_sen_0b01",
+            Self::Sen10 => "se_sen_0b10",
+            Self::Sen11 => "se_sen_0b11",
+            Self::StageEnter => "se_stage_enter",
+            Self::UpTemp => "se_up_temp",
         }
     }
 }
 
-#[derive(Debug, Default)]
+/// 音を鳴らす、実際の再生は audio::Mixer が行う
 struct Sound {
-    playing_sound: Vec<SoundKind>,
+    mixer: audio::Mixer,
+    manifest: audio::SoundManifest,
+    playing_sound: Vec<String>,     // play_loop で鳴らしている音
 }
 impl Sound {
-    fn is_playing(&self, sound_kind: &SoundKind) -> bool {
-        self.playing_sound.iter().any(|sk| sk == sound_kind)
+    const MANIFEST_FILE_PATH: &'static str = "/data/sound_manifest.json";
+    const CROSSFADE_SECONDS: f64 = 1.0;
+    const CLOSENESS_BASE_FREQUENCY: f64 = 330.0;
+
+    fn new() -> Self {
+        let own = Self {
+            mixer: audio::Mixer::new(),
+            manifest: Self::load_manifest(),
+            playing_sound: vec![],
+        };
+        for (name, asset) in &own.manifest.sounds {
+            own.mixer.load(name, &format!("/resource/sound/{}", asset.file));
+            own.set_volume(name, asset.volume);
+        }
+        own.apply_settings(&Resource::user_storage().settings.audio);
+
+        own
+    }
+
+    /// 音源の一覧が読めなくても遊べるので、音を鳴らさないようにする
+    fn load_manifest() -> audio::SoundManifest {
+        let file = js::fs::open( Self::MANIFEST_FILE_PATH.to_string() );
+        let reader = std::io::BufReader::new(file);
+
+        serde_json::from_reader(reader).unwrap_or_else(|e| {
+            js::console_log!("invalid {} E:{:?}", Self::MANIFEST_FILE_PATH, e);
+            audio::SoundManifest::default()
+        })
+    }
+
+    /// 音量の設定を全ての音に反映する
+    fn apply_settings(&self, audio_settings: &settings::AudioSettings) {
+        let master_volume = if audio_settings.is_muted { 0.0 } else { audio_settings.master_volume };
+        self.mixer.set_master_volume(master_volume);
+        self.mixer.set_bus_volume(audio::Bus::Bgm, audio_settings.bgm_volume);
+        self.mixer.set_bus_volume(audio::Bus::Se, audio_settings.se_volume);
     }
 
-    fn play_loop(&mut self, sound_kind: SoundKind) {
-        if self.is_playing(&sound_kind) {
+    fn is_playing(&self, name: &str) -> bool {
+        self.playing_sound.iter().any(|playing| playing == name)
+    }
+
+    /// name の音をループで鳴らす、既に鳴らしていれば何もしない
+    /// BGM は鳴っている BGM とクロスフェードする
+    fn play_loop(&mut self, name: &str) {
+        if self.is_playing(name) {
             return;
         }
 
-        if let Some(audio_element) = js::dom::get_element_by_id::<HtmlMediaElement>(sound_kind.get_id()) {
-            audio_element.set_loop(true);
+        let (bus, ducks_bgm) = match self.manifest.sounds.get(name) {
+            Some(asset) => (asset.bus, asset.ducks_bgm),
+            None => {
+                js::console_log!("sound {} is not in {}", name, Self::MANIFEST_FILE_PATH);
+                return;
+            },
+        };
+        let mut options = audio::PlayOptions {
+            is_loop: true,
+            ducks_bgm,
+            ..Default::default()
+        };
+        if audio::Bus::Bgm == bus {
+            self.fade_out_bgm();
+            options.fade_in = Self::CROSSFADE_SECONDS;
         }
-        self.play(sound_kind);
+
+        self.playing_sound.push(name.to_string());
+        self.mixer.play(name, bus, options);
     }
     fn play(&mut self, sound_kind: SoundKind) {
-        if let Some(audio_element) = js::dom::get_element_by_id::<HtmlMediaElement>(sound_kind.get_id()) {
-            if !self.is_playing(&sound_kind) {
-                self.playing_sound.push(sound_kind.clone());
-            }
-
-            audio_element.set_current_time(0.0);
-            let _ = audio_element.play();
-            
-            js::console_log!("play {:?}", sound_kind.get_id());
+        self.play_at_rate(sound_kind, 1.0);
+    }
+    /// playback_rate の速さ (高さ) で鳴らす
+    fn play_at_rate(&mut self, sound_kind: SoundKind, playback_rate: f64) {
+        if let Some(asset) = self.manifest.sounds.get(sound_kind.get_id()) {
+            let options = audio::PlayOptions {
+                ducks_bgm: asset.ducks_bgm,
+                playback_rate,
+                ..Default::default()
+            };
+            self.mixer.play(sound_kind.get_id(), asset.bus, options);
+        } else {
+            js::console_log!("sound {} is not in {}", sound_kind.get_id(), Self::MANIFEST_FILE_PATH);
         }
     }
 
-    fn pause(&mut self, sound_kind: SoundKind) {
-        if let Some(index) = self.playing_sound.iter().position(|sk| *sk == sound_kind) {
-            self.playing_sound.remove(index);
+    /// 温度の変化を音で伝える (画面を見なくても進み具合が分かるように)
+    /// 上下の SE は変化が大きいほど高く鳴らし、続けて目標の温度に近づいたら上がる音、遠ざかったら下がる音を鳴らす
+    fn play_temperature(&mut self, prev_temperature: i32, temperature: i32, target_temperature: i32, max_temperature: i32) {
+        if temperature == prev_temperature || max_temperature <= 0 {
+            return;
         }
 
-        if let Some(audio_element) = js::dom::get_element_by_id::<HtmlMediaElement>(sound_kind.get_id()) {
-            if !audio_element.ended() {
-                audio_element.set_current_time(0.0);
-                let _ = audio_element.pause();
-            }
-            js::console_log!("pause {:?}", sound_kind.get_id());
-        }
-    }
+        let moved = (temperature - prev_temperature).abs() as f64 / max_temperature as f64;
+        let sound_kind = if temperature < prev_temperature { SoundKind::DownTemp } else { SoundKind::UpTemp };
+        self.play_at_rate(sound_kind, 1.0 + 0.5 * moved);
 
+        // 目標との近さ (0.0 ~ 1.0) を 1 オクターブの高さにする
+        let frequency = |temperature: i32| {
+            let closeness = 1.0 - (target_temperature - temperature).abs() as f64 / max_temperature as f64;
+            (Self::CLOSENESS_BASE_FREQUENCY * 2.0_f64.powf(closeness)) as f32
+        };
+        self.mixer.play_tone(audio::Tone {
+            from_frequency: frequency(prev_temperature),
+            to_frequency: frequency(temperature),
+            volume: 0.3,
+            delay: 0.15,
+            duration: 0.25,
+        }, audio::Bus::Se);
+    }
+
+    /// 鳴らしている音を全て止める
+    /// 画面を切り替える時は BGM をクロスフェードさせるので fade_out_bgm を使う
+    #[allow(dead_code)]
     fn pause_all(&mut self) {
-        while let Some(sk) = self.playing_sound.pop() {
-            self.pause(sk);
-        }
+        self.playing_sound.clear();
+        self.mixer.stop_all();
     }
 
-    fn set_volume(&self, sound_kind: SoundKind, volume: f64) {
-        if let Some(audio_element) = js::dom::get_element_by_id::<HtmlMediaElement>(sound_kind.get_id()) {
-            audio_element.set_volume(volume);
-        }
+    /// 鳴らしている BGM をフェードアウトする
+    /// 次の画面の BGM はフェードインするので、画面を切り替える前に呼ぶとクロスフェードになる
+    fn fade_out_bgm(&mut self) {
+        let manifest = &self.manifest;
+        self.playing_sound.retain(|playing| manifest.sounds.get(playing).is_none_or(|asset| audio::Bus::Bgm != asset.bus));
+        self.mixer.fade_out(audio::Bus::Bgm, Self::CROSSFADE_SECONDS);
     }
-}
-impl PageTrait for Sound {
-    fn view(&self, _link: &ComponentLink<MainModel>) -> Html {
-        html!{
-            <>
-                { for SoundKind::iter().map(|sound_kind| sound_kind.get_audio_html()) }
-            </>
-        }
+
+    fn set_volume(&self, name: &str, volume: f64) {
+        self.mixer.set_track_volume(name, volume);
     }
 }
 
@@ -617,6 +1200,10 @@ struct Resource {
     onsen_status_manager: Option<OnsenStatusManager>,   // ステージ情報
     user_storage: Option<UserStorage>,                  // ユーザー情報
     sound: Option<Sound>,                               // 音
+    storage: Option<Box<dyn storage::Storage>>,         // セーブデータの保存先
+    onsen_dictionary: Option<onsen_dictionary::OnsenDictionary>, // 温泉名の辞書
+    tutorial_book: Option<tutorial::TutorialBook>,      // ステージ毎のチュートリアル
+    reachable_names: BTreeMap<String, ReachableNames>,  // ステージ毎の見つけられる温泉名 (探索に時間がかかるので一度だけ探す)
 }
 impl Resource {
     fn get_onsen_status_manager(&mut self) -> &OnsenStatusManager {
@@ -637,12 +1224,75 @@ impl Resource {
 
     fn get_sound(&mut self) -> &mut Sound {
         if self.sound.is_none() {
-            self.sound = Some(Sound::default());
+            self.sound = Some(Sound::new());
         }
 
         self.sound.as_mut().unwrap()
     }
 
+    fn get_storage(&mut self) -> &mut dyn storage::Storage {
+        if self.storage.is_none() {
+            self.storage = Some(storage::default_storage());
+        }
+
+        self.storage.as_mut().unwrap().as_mut()
+    }
+
+    fn get_onsen_dictionary(&mut self) -> &onsen_dictionary::OnsenDictionary {
+        if self.onsen_dictionary.is_none() {
+            self.onsen_dictionary = Some(Self::load_onsen_dictionary());
+        }
+
+        self.onsen_dictionary.as_ref().unwrap()
+    }
+
+    const ONSEN_DICTIONARY_FILE_PATH: &'static str = "/data/onsen_name_dictionary.json";
+
+    /// 辞書が読めなくても温泉名はひらがなで付けられるので、空の辞書にする
+    fn load_onsen_dictionary() -> onsen_dictionary::OnsenDictionary {
+        let file = js::fs::open( Self::ONSEN_DICTIONARY_FILE_PATH.to_string() );
+        let reader = std::io::BufReader::new(file);
+
+        serde_json::from_reader(reader).unwrap_or_else(|e| {
+            js::console_log!("invalid {} E:{:?}", Self::ONSEN_DICTIONARY_FILE_PATH, e);
+            onsen_dictionary::OnsenDictionary::default()
+        })
+    }
+
+    fn get_tutorial_book(&mut self) -> &tutorial::TutorialBook {
+        if self.tutorial_book.is_none() {
+            self.tutorial_book = Some(Self::load_tutorial_book());
+        }
+
+        self.tutorial_book.as_ref().unwrap()
+    }
+
+    const TUTORIAL_BOOK_FILE_PATH: &'static str = "/data/tutorials.json";
+
+    /// 読めなければチュートリアル無しで遊べるようにする
+    fn load_tutorial_book() -> tutorial::TutorialBook {
+        let file = js::fs::open( Self::TUTORIAL_BOOK_FILE_PATH.to_string() );
+        let reader = std::io::BufReader::new(file);
+
+        serde_json::from_reader(reader).unwrap_or_else(|e| {
+            js::console_log!("invalid {} E:{:?}", Self::TUTORIAL_BOOK_FILE_PATH, e);
+            tutorial::TutorialBook::default()
+        })
+    }
+
+    fn get_reachable_names(&mut self, key: &str) -> &ReachableNames {
+        if !self.reachable_names.contains_key(key) {
+            let reachable_names = OnsenStatus::get_onsen_status_from_name(key).search_reachable_names();
+            self.reachable_names.insert(key.to_string(), reachable_names);
+        }
+
+        &self.reachable_names[key]
+    }
+
+    fn get_cached_reachable_names(&self, key: &str) -> Option<&ReachableNames> {
+        self.reachable_names.get(key)
+    }
+
     pub fn onsen_status_manager<'a>() -> &'a OnsenStatusManager {
         unsafe{ RESOURCE.get_onsen_status_manager() }
     }
@@ -654,12 +1304,48 @@ impl Resource {
     pub fn sound<'a>() -> &'a mut Sound {
         unsafe{ RESOURCE.get_sound() }
     }
-}
-static mut RESOURCE: Resource = Resource {
-    onsen_status_manager: None,
-    user_storage: None,
-    sound: None,
-};
+
+    pub fn storage<'a>() -> &'a mut dyn storage::Storage {
+        unsafe{ RESOURCE.get_storage() }
+    }
+
+    pub fn onsen_dictionary<'a>() -> &'a onsen_dictionary::OnsenDictionary {
+        unsafe{ RESOURCE.get_onsen_dictionary() }
+    }
+
+    pub fn tutorial_book<'a>() -> &'a tutorial::TutorialBook {
+        unsafe{ RESOURCE.get_tutorial_book() }
+    }
+
+    /// 探していなければ探すので時間がかかる、描画中には呼ばない
+    pub fn reachable_names<'a>(key: &str) -> &'a ReachableNames {
+        unsafe{ RESOURCE.get_reachable_names(key) }
+    }
+
+    /// 探し終えていれば見つけられる温泉名を返す (探さない)
+    pub fn cached_reachable_names<'a>(key: &str) -> Option<&'a ReachableNames> {
+        unsafe{ RESOURCE.get_cached_reachable_names(key) }
+    }
+
+    /// 設定の配色 (読み上げる色の名前に使う)
+    pub fn palette() -> settings::Palette {
+        Self::user_storage().settings.theme.palette
+    }
+
+    /// 温泉マークの画像のパス (設定の配色、模様による)
+    pub fn mark_path(file_name: &str) -> String {
+        Self::user_storage().settings.theme.mark_path(file_name)
+    }
+}
+static mut RESOURCE: Resource = Resource {
+    onsen_status_manager: None,
+    user_storage: None,
+    sound: None,
+    storage: None,
+    onsen_dictionary: None,
+    tutorial_book: None,
+    reachable_names: BTreeMap::new(),
+};
 
 /// ページ遷移用の雛形
 trait PageTrait {
@@ -667,74 +1353,232 @@ trait PageTrait {
 
     fn update(&mut self, _message: Message) -> Message { Message::None }
     fn rendered(&mut self, _first_render: bool) {}
+    // 設定のページから戻ってきた時
+    fn resumed(&mut self) {}
 }
 
 struct SelectPage {
     stage_number: i32,
     stage_level: i32,
     saved_onsen_data: &'static OnsenStatus,
+    is_scroll_pending: bool,    // 描画後に、最初のステージが見えるように一番下までスクロールする
+    locked_stage_number: Option<i32>,   // 選ぼうとした開いていないステージ (開くのに足りないものを表示する)
 }
 impl SelectPage {
     fn new() -> Self {
+        let saved_onsen_data = OnsenStatus::get_init_onsen_status();
         let own = Self {
             stage_number: -1,
-            stage_level: Resource::user_storage().init_stage_level,
-            saved_onsen_data: OnsenStatus::get_init_onsen_status()
+            // 最後に遊んだステージのレベルを開く
+            stage_level: saved_onsen_data.stage_level().unwrap_or(Resource::user_storage().init_stage_level),
+            saved_onsen_data,
+            is_scroll_pending: true,
+            locked_stage_number: None,
         };
         own.play_bgm();
 
         own
     }
 
-    fn play_bgm(&self) {
-        let bgm_kind = match self.stage_level+1 {
-            2 => SoundKind::BgmStageLevel2,
-            _ => SoundKind::BgmStageLevel1,
-        };
+    /// レベルを開くのに足りないものの説明を返す、開いていれば空
+    fn level_missing_requirements(stage_level: i32) -> Vec<String> {
+        unlock::missing_requirements(&Resource::onsen_status_manager().level_unlock_rules(stage_level), Resource::user_storage())
+    }
 
-        Resource::sound().set_volume(bgm_kind, 0.5);
-        Resource::sound().play_loop(bgm_kind);
+    /// ステージが開いているか
+    fn is_stage_unlocked(status: &OnsenStatus) -> bool {
+        status.missing_requirements().is_empty()
     }
 
-    const NOT_CLEAR_ONSEN_MARK: &'static str = "/resource/image/mark_offsen.png";
-    const CLEAR_ONSEN_MARK: &'static str = "/resource/image/mark_orsen.png";
-    const ALL_CLEAR_ONSEN_MARK: &'static str = "/resource/image/mark_onsen.png";
-}
-impl PageTrait for SelectPage {
-    fn view(&self, link: &ComponentLink<MainModel>) -> Html {
-        // ステージ毎の簡易表示(クリア情報と遷移先)
-        let mut select_container_item_content_html = vec![];
-        let mut all_complete = true;
-        for &stage_level in &Resource::onsen_status_manager().level_list {
-            for stage_number in 0..4 {
-                let temp_status = OnsenStatus::get_onsen_status(stage_level, stage_number);
-
-                if all_complete {
-                    all_complete = temp_status.is_complete();
-                }
-                if stage_level != self.stage_level {
-                    continue
-                }
+    /// レベルで獲得した☆の数と、☆の総数
+    fn level_stars(stage_level: i32) -> (i32, i32) {
+        let stage_numbers = Resource::onsen_status_manager().stage_numbers(stage_level);
+        let stars = stage_numbers.iter().map(|&stage_number| OnsenStatus::get_onsen_status(stage_level, stage_number).star_count()).sum();
 
-                let clear_mark = if all_complete {
-                    Self::ALL_CLEAR_ONSEN_MARK
-                } else if temp_status.is_cleared() {
-                    Self::CLEAR_ONSEN_MARK
-                } else {
-                    Self::NOT_CLEAR_ONSEN_MARK
-                };
+        (stars, 3 * stage_numbers.len() as i32)
+    }
+
+    /// 表示するレベルを変える
+    fn select_level(&mut self, stage_level: i32) {
+        if stage_level == self.stage_level {
+            return;
+        }
+        js::console_log!("to level {}", stage_level);
+
+        if -1 != self.stage_number {
+            OnsenStatus::hide_popup();
+            self.stage_number = -1;
+        }
+        self.stage_level = stage_level;
+        self.is_scroll_pending = true;
+        self.locked_stage_number = None;
+        Resource::user_storage().init_stage_level = stage_level;
+        Resource::user_storage().save_data();
+        self.play_bgm();
+    }
 
-                select_container_item_content_html.push(html!{
-                    <div class={ format!("stage_{}", stage_number) }>
-                        <img class="is_clear_onsen_mark" src={ clear_mark } alt="onsen_mark"
-                            ontouchend=link.callback( move |event| Message::StageSelect(stage_number) )
+    /// 開いていないステージを選んだ状態にして、開くのに足りないものを表示する
+    fn show_locked_stage(&mut self, key: &str) {
+        let status = OnsenStatus::get_onsen_status_from_name(key);
+        let stage_number = key.rsplit('_').next().and_then(|stage_number| stage_number.parse().ok());
+        if let (Some(stage_level), Some(stage_number)) = (status.stage_level(), stage_number) {
+            self.select_level(stage_level);
+            self.locked_stage_number = Some(stage_number);
+        }
+    }
+
+    /// 前後のレベル (offset: -1 or 1) に変える
+    fn select_level_by_offset(&mut self, offset: i32) {
+        let level_list = &Resource::onsen_status_manager().level_list;
+        if let Some(index) = level_list.iter().position(|&level| level == self.stage_level) {
+            let index = index as i32 + offset;
+            if 0 <= index && (index as usize) < level_list.len() {
+                self.select_level(level_list[index as usize]);
+            }
+        }
+    }
+
+    /// ステージの一覧 (下から上へ、左右交互に並べる)
+    fn get_stage_map_html(&self, link: &ComponentLink<MainModel>) -> Html {
+        let stage_numbers = Resource::onsen_status_manager().stage_numbers(self.stage_level);
+        let stage_count = stage_numbers.len();
+
+        let stages_html = stage_numbers.iter().enumerate().map(|(index, &stage_number)| {
+            let status = OnsenStatus::get_onsen_status(self.stage_level, stage_number);
+            let (clear_mark, state_class, state_label) = if !Self::is_stage_unlocked(status) {
+                (Self::NOT_CLEAR_ONSEN_MARK, "stage_locked", "未開放")
+            } else if status.is_complete() {
+                (Self::ALL_CLEAR_ONSEN_MARK, "stage_complete", "☆コンプリート")
+            } else if status.is_cleared() {
+                (Self::CLEAR_ONSEN_MARK, "stage_cleared", "クリア済み")
+            } else {
+                (Self::NOT_CLEAR_ONSEN_MARK, "stage_not_cleared", "未クリア")
+            };
+            let style = format!("grid-row: {}; grid-column: {};", stage_count - index, if 0 == index % 2 { 2 } else { 4 });
+
+            html!{
+                <div class={ format!("stage_{} {}", stage_number, state_class) } style={ style }>
+                    // キーボードでもフォーカスして Enter/Space で選べるようにボタンにする
+                    <button type="button" class="select_stage_button"
+                        onclick=link.callback( move |event| Message::StageSelect(stage_number) )>
+                        <img class="is_clear_onsen_mark" src={ Resource::mark_path(clear_mark) }
+                            alt={ format!("ステージ {} {} ☆{}", index + 1, state_label, status.star_count()) }
                         />
-                    </div>
-                });
+                    </button>
+                </div>
+            }
+        });
+
+        html!{
+            <div id="select_stage_map">
+                { for stages_html }
+                { self.get_lock_notice_html(&stage_numbers) }
+            </div>
+        }
+    }
+
+    /// 開いていないレベルや、選んだ開いていないステージを開くのに足りないもの
+    fn get_lock_notice_html(&self, stage_numbers: &[i32]) -> Html {
+        let (title, requirements) = match self.locked_stage_number {
+            Some(stage_number) => (
+                format!("ステージ {} を開くには", stage_numbers.iter().position(|&number| number == stage_number).map_or(0, |index| index + 1)),
+                OnsenStatus::get_onsen_status(self.stage_level, stage_number).missing_requirements(),
+            ),
+            None => ("このレベルを開くには".to_string(), Self::level_missing_requirements(self.stage_level)),
+        };
+        if requirements.is_empty() {
+            return html! {};
+        }
+
+        html! {
+            <div id="select_lock_notice" role="status">
+                <p>{ title }</p>
+                <ul>
+                    { for requirements.iter().map(|requirement| html! { <li>{ requirement }</li> }) }
+                </ul>
+            </div>
+        }
+    }
+
+    /// レベルの切り替え (レベル毎の☆の合計も表示する)
+    fn get_level_switcher_html(&self, link: &ComponentLink<MainModel>) -> Html {
+        let levels_html = Resource::onsen_status_manager().level_list.iter().map(|&stage_level| {
+            let (stars, max_stars) = Self::level_stars(stage_level);
+            let label = if Self::level_missing_requirements(stage_level).is_empty() {
+                format!("Lv.{} ☆{}/{}", stage_level + 1, stars, max_stars)
+            } else {
+                format!("Lv.{} 🔒", stage_level + 1)
+            };
+
+            html!{
+                <button type="button"
+                    class={ if stage_level == self.stage_level { "select_level_button selected_level" } else { "select_level_button" } }
+                    aria-pressed={ (stage_level == self.stage_level).to_string() }
+                    onclick=link.callback(move |_| Message::SelectLevel(stage_level))
+                >
+                    { label }
+                </button>
             }
+        });
+
+        html!{
+            <div id="select_level_switcher" role="group" aria-label="レベル">
+                { for levels_html }
+            </div>
         }
+    }
+
+    /// quastions.json でレベル毎に指定された BGM を鳴らす、無ければ sound_manifest.json の BGM
+    fn play_bgm(&self) {
+        let bgm = match Resource::onsen_status_manager().level_bgm.get(&self.stage_level.to_string()) {
+            Some(bgm) => bgm.clone(),
+            None => Resource::sound().manifest.level_bgm(self.stage_level).to_string(),
+        };
+
+        Resource::sound().play_loop(&bgm);
+    }
 
-        if all_complete && !OnsenStatus::is_show_popup() {
+    /// キーボードでの操作
+    /// ←/→: 前後のステージ, ↑/↓: 前後のレベル, 1~9: ステージを選ぶ, Enter/Space: ステージを開く・入る, Escape: 閉じる
+    fn on_key_down(&mut self, key: &str) -> Message {
+        let is_show_popup = OnsenStatus::is_show_popup();
+
+        match key {
+            "ArrowLeft" | "ArrowRight" | "Enter" | " " if !is_show_popup => {
+                // 最後に選んだステージを開く
+                OnsenStatus::show_popup();
+                self.stage_number = i32::MAX;
+                Message::None
+            },
+            "ArrowUp" if !is_show_popup => {
+                self.select_level_by_offset(1);
+                Message::None
+            },
+            "ArrowDown" if !is_show_popup => {
+                self.select_level_by_offset(-1);
+                Message::None
+            },
+            "ArrowLeft" => self.update(Message::StageBack),
+            "ArrowRight" => self.update(Message::StageNext),
+            "Enter" | " " => self.update(Message::StageEnter),
+            "Escape" => self.update(Message::StageClose),
+            _ => match key.parse::<usize>().ok().and_then(|number| Resource::onsen_status_manager().stage_numbers(self.stage_level).get(number.wrapping_sub(1)).copied()) {
+                Some(stage_number) => self.update(Message::StageSelect(stage_number)),
+                None => Message::None,
+            },
+        }
+    }
+
+    const NOT_CLEAR_ONSEN_MARK: &'static str = "mark_offsen.png";
+    const CLEAR_ONSEN_MARK: &'static str = "mark_orsen.png";
+    const ALL_CLEAR_ONSEN_MARK: &'static str = "mark_onsen.png";
+}
+impl PageTrait for SelectPage {
+    fn view(&self, link: &ComponentLink<MainModel>) -> Html {
+        let manager = Resource::onsen_status_manager();
+        if !OnsenStatus::is_show_popup() && manager.level_list.iter().all(|&stage_level| {
+            manager.stage_numbers(stage_level).iter().all(|&stage_number| OnsenStatus::get_onsen_status(stage_level, stage_number).is_complete())
+        }) {
             js::console_log!("all complete");
             link.send_message(Message::AllComplete);
         }
@@ -743,17 +1587,33 @@ impl PageTrait for SelectPage {
             <div class="select_container" id="grand_parent_node">
                 <div class="container_item_header">
                     /*{ "banner_area" }*/
+                    <button type="button" id="statistics_button"
+                        onclick=link.callback(|_| Message::ChangeToStatisticsPage)
+                    >
+                        { "統計" }
+                    </button>
+                    <button type="button" id="gallery_button"
+                        onclick=link.callback(|_| Message::ChangeToGalleryPage)
+                    >
+                        { "図鑑" }
+                    </button>
+                    <button type="button" id="settings_button"
+                        onclick=link.callback(|_| Message::ChangeToSettingsPage)
+                    >
+                        { "設定" }
+                    </button>
                 </div>
                 <div class="select_container_item_content">
-                    { for select_container_item_content_html }
+                    { self.get_stage_map_html(link) }
                     { self.saved_onsen_data.get_popup_html(link, true) }
                 </div>
                 <div class="container_item_footer">
                     /*{ "banner_area" }*/
+                    { self.get_level_switcher_html(link) }
                 </div>
                 <img id="onsen_select_map"
                     src="/resource/image/onsen_select_map.png"
-                    ontouchend=link.callback(|event| Message::StageClose)
+                    onclick=link.callback(|event| Message::StageClose)
                 />
             </div>
         }
@@ -764,32 +1624,49 @@ impl PageTrait for SelectPage {
 
         match message {
             Message::StageEnter => {
+                if !Self::is_stage_unlocked(self.saved_onsen_data) {
+                    js::console_log!("stage {} is locked", self.saved_onsen_data.key);
+                    return Message::None;
+                }
                 return Message::ChangeToQuastionPage(
                     self.saved_onsen_data.key.clone()
                 );
             },
-            Message::StageBack => {
-                if self.saved_onsen_data.back_key() != self.saved_onsen_data.key {
-                    js::console_log!("to stage {}", self.saved_onsen_data.back_key());
+            Message::StageBack | Message::StageNext => {
+                let key = if let Message::StageBack = message { self.saved_onsen_data.back_key() } else { self.saved_onsen_data.next_key() };
+                let status = OnsenStatus::get_onsen_status_from_name(&key);
+                if key != self.saved_onsen_data.key && Self::is_stage_unlocked(status) {
+                    js::console_log!("to stage {}", key);
                     self.stage_number = i32::MAX;
-                    self.saved_onsen_data = OnsenStatus::get_onsen_status_from_name(&self.saved_onsen_data.back_key());
+                    self.saved_onsen_data = status;
+                    // 別のレベルのステージに移ったら、一覧もそのレベルにする
+                    if let Some(stage_level) = status.stage_level() {
+                        if stage_level != self.stage_level {
+                            self.stage_level = stage_level;
+                            self.is_scroll_pending = true;
+                            self.play_bgm();
+                        }
+                    }
                 }
             },
-            Message::StageNext => {
-                if self.saved_onsen_data.next_key() != self.saved_onsen_data.key {
-                    js::console_log!("to stage {}", self.saved_onsen_data.next_key());
-                    self.stage_number = i32::MAX;
-                    self.saved_onsen_data = OnsenStatus::get_onsen_status_from_name(&self.saved_onsen_data.next_key());
-                }
+            Message::SelectLevel(stage_level) => {
+                self.select_level(stage_level);
             },
             Message::StageSelect(stage_number) => {
+                if !Self::is_stage_unlocked(OnsenStatus::get_onsen_status(self.stage_level, stage_number)) {
+                    js::console_log!("stage {} is locked", stage_number);
+                    self.locked_stage_number = Some(stage_number);
+                    return Message::None;
+                }
                 js::console_log!("to stage {}", stage_number);
+                self.locked_stage_number = None;
 
                 OnsenStatus::show_popup();
                 self.stage_number = stage_number;
                 self.saved_onsen_data = OnsenStatus::get_onsen_status(self.stage_level, self.stage_number);
             },
             Message::StageClose => {
+                self.locked_stage_number = None;
                 if -1 == self.stage_number {
                     return Message::None;
                 }
@@ -811,6 +1688,18 @@ impl PageTrait for SelectPage {
                 js::console_log!("all complete");
                 return Message::ChangeToQuastionPage("complete".to_string());
             },
+            Message::ChangeToStatisticsPage => {
+                return Message::ChangeToStatisticsPage;
+            },
+            Message::ChangeToGalleryPage => {
+                return Message::ChangeToGalleryPage;
+            },
+            Message::ChangeToSettingsPage => {
+                return Message::ChangeToSettingsPage;
+            },
+            Message::KeyDown(key) => {
+                return self.on_key_down(&key);
+            },
             _ => (),
         };
 
@@ -819,6 +1708,17 @@ impl PageTrait for SelectPage {
 
     fn rendered(&mut self, _first_render: bool) {
         self.saved_onsen_data.rendered();
+
+        if self.is_scroll_pending {
+            self.is_scroll_pending = false;
+            if let Some(stage_map) = js::dom::get_element_by_id::<HtmlElement>("select_stage_map") {
+                stage_map.set_scroll_top(stage_map.scroll_height());
+            }
+        }
+    }
+
+    fn resumed(&mut self) {
+        self.is_scroll_pending = true;
     }
 }
 
@@ -828,24 +1728,51 @@ struct QuastionPage {
     quastion: &'static OnsenStatus,
     now_status: OnsenStatus,
     cursor_image: Option<HtmlImageElement>,
+    drag: Option<drag::DragController>,
+    hit_masks: HashMap<String, drag::HitMask>,  // sen の画像毎の当たり判定
     first_page: bool,
     opened_door: bool,
+    is_reopening_door: bool,    // 設定のページから戻ってきて、作り直された扉を開け直す
+    started_at: f64,
+    selected_sen: Option<usize>,                // キーボードで選んでいる sen
+    announcement: String,                       // スクリーンリーダーに読み上げさせる、直前の操作の結果
+    tutorial: Option<tutorial::TutorialRunner>, // 進めているチュートリアル (終えていれば None)
+    history: Vec<(sen::SenOpManager, OnsenStatus)>, // 元に戻すための、SenOp を使う前の状態
+    later: yew::Callback<Message>,              // 描画の後で時間のかかる処理をするためのメッセージを送る
 }
 impl QuastionPage {
+    const INITIAL_SEN_OP_LIST: [sen::SenOp; 5] = [sen::SenOp::Off, sen::SenOp::On, sen::SenOp::Or, sen::SenOp::And, sen::SenOp::Not];
     const HINT_PARAM_NAME: &'static str = "hint";
     const POPUP_PARAM_NAME: &'static str = "show_popup";
 
-    fn new(name: &str) -> Self {
+    fn new(name: &str, later: &yew::Callback<Message>) -> Self {
         let mut own = Self {
-            sen_op: sen::SenOpManager::new( vec![sen::SenOp::Off, sen::SenOp::On, sen::SenOp::Or, sen::SenOp::And, sen::SenOp::Not], Some(0) ),
+            sen_op: sen::SenOpManager::new( Self::INITIAL_SEN_OP_LIST.to_vec(), Some(0) ),
             quastion: &Resource::onsen_status_manager().onsen_status_list[name],
             now_status: OnsenStatus::new(name),
             cursor_image: None,
+            drag: None,
+            hit_masks: HashMap::new(),
             first_page: false,
             opened_door: false,
+            is_reopening_door: false,
+            started_at: js::time::now().as_f64().unwrap(),
+            selected_sen: None,
+            announcement: String::new(),
+            tutorial: None,
+            history: vec![],
+            later: later.clone(),
         };
         own.load_quastion(name);
 
+        // 終えていないチュートリアルがあれば始める
+        if let Some(steps) = Resource::tutorial_book().scripts.get(name) {
+            let runner = tutorial::TutorialRunner::new(name, steps.clone());
+            if !Resource::user_storage().is_tutorial_completed(name) && !runner.is_finished() {
+                own.tutorial = Some(runner);
+            }
+        }
+
         if let Some(hint_value) = js::dom::get_param(Self::HINT_PARAM_NAME) {
             if "off" == &hint_value {
                 own.now_status.use_hint_and_save();
@@ -863,8 +1790,24 @@ impl QuastionPage {
         own
     }
 
-    fn new_from_localstorage() -> Self {
-        let mut own = Self::new(&Resource::user_storage().init_onsen_key);
+    /// 統計に記録する、top などのステージ以外は数えない
+    fn record_statistics<F: FnOnce(&mut statistics::StageStatistics)>(&self, f: F) {
+        let key = &self.now_status.key;
+        if !key.starts_with("stage_") {
+            return;
+        }
+
+        f(Resource::user_storage().statistics(key));
+    }
+
+    /// ステージに挑戦したことを統計に記録する
+    fn record_attempt(&self) {
+        self.record_statistics(|statistics| statistics.record_attempt());
+        Resource::user_storage().save_data();
+    }
+
+    fn new_from_localstorage(later: &yew::Callback<Message>) -> Self {
+        let mut own = Self::new(&Resource::user_storage().init_onsen_key, later);
         own.first_page = true;
 
         own
@@ -897,52 +1840,96 @@ impl QuastionPage {
                 </div>
                 <div id="onsen_mark_frame_div">
                     <img id="onsen_mark_frame" src="/resource/image/onsen_mark_frame.png" alt="onsen_mark_frame" />
-                    <div id="onsen_mark_sen">
+                    <div id="onsen_mark_sen" role="group" aria-label="温泉マークの線">
                     </div>
                 </div>
                 <img id="tutorial_cursor" src="/resource/image/computer_cursor_finger_white.png" alt="tutorial_cursor" />
+                { self.get_tutorial_text_html(link) }
+
+                { self.get_text_state_html() }
+                <div id="live_region" class="visually_hidden" role="status" aria-live="polite">{ &self.announcement }</div>
             </div>
         }
     }
 
     /// 温泉マークを移動したときに呼ぶ
-    fn moved_onsen_mark(&mut self) -> Message {
-        // ドロップ時に sen と重なっているかを判断して、重なってるなら演算をする
-        let mut x: f64 = 0.0;
-        let mut y: f64 = 0.0;
-        if let Some(image) = &self.cursor_image {
-            // event から座標が取れなかったので(TouchEndだからカーソルの座標がない？)、TouchMove で動かしてた css から取る
-            let left: String = image.style().get_property_value("left").unwrap();
-            x = left.split_at( left.len() - 2 ).0.parse::<f64>().unwrap();
-            let top: String = image.style().get_property_value("top").unwrap();
-            y = top.split_at( top.len() - 2 ).0.parse::<f64>().unwrap();
+    /// (x, y) に落とした時に重なっている sen を返す
+    fn hit_test(&self, x: f64, y: f64) -> drag::HitResult {
+        let mut sen_images = vec![];
+        self.now_status.sen.for_each(|(index, _sen)| {
+            if let Some(sen_image) = js::dom::get_element_by_id::<HtmlImageElement>(&format!("bit_{}", index)) {
+                sen_images.push((index, sen_image));
+            }
+        });
+
+        let targets: Vec<drag::HitTarget> = sen_images.iter()
+            .map(|(index, sen_image)| {
+                let rect = sen_image.get_bounding_client_rect();
+                drag::HitTarget {
+                    index: *index,
+                    left: rect.x(),
+                    top: rect.y(),
+                    width: rect.width(),
+                    height: rect.height(),
+                    mask: self.hit_masks.get(&sen_image.current_src()),
+                }
+            })
+            .collect();
+
+        drag::hit_test(&targets, x, y)
+    }
+
+    /// sen の画像の当たり判定を作っておく (色が変わると画像も変わるので、ドラッグを始める度に確かめる)
+    fn prepare_hit_masks(&mut self) {
+        for index in 0..self.now_status.sen.len() {
+            if let Some(sen_image) = js::dom::get_element_by_id::<HtmlImageElement>(&format!("bit_{}", index)) {
+                if let std::collections::hash_map::Entry::Vacant(entry) = self.hit_masks.entry(sen_image.current_src()) {
+                    if let Some(mask) = drag::HitMask::from_image(&sen_image) {
+                        entry.insert(mask);
+                    }
+                }
+            }
         }
+    }
+
+    /// 温泉マークを (x, y) にドロップしたときに呼ぶ
+    fn moved_onsen_mark(&mut self, x: f64, y: f64) -> Message {
+        // ドラッグ中の温泉マークを削除
+        if let Some(image) = self.cursor_image.take() {
+            image.remove();
+        }
+
+        // ドロップ時に sen と重なっているかを判断して、重なってるなら演算をする ((On || Off) は全てに適用するのでどこでも良い)
+        let sen_op_top = self.sen_op.get_top();
+        match self.hit_test(x, y) {
+            drag::HitResult::Sen(target) => self.apply_sen_op(Some(target)),
+            drag::HitResult::NoTarget if sen::SenOp::On == sen_op_top || sen::SenOp::Off == sen_op_top => self.apply_sen_op(None),
+            drag::HitResult::NoTarget => {
+                js::console_log!("no target at ({}, {})", x, y);
+                self.announcement = "線に重なっていません。".to_string();
+                Message::None
+            },
+        }
+    }
+
+    /// 選んでいる SenOp を is_target が true を返す sen に適用する ((On || Off) は全てに適用)
+    fn apply_sen_op(&mut self, target: Option<usize>) -> Message {
+        self.history.push((self.sen_op.clone(), self.now_status.clone()));
 
         let sen_op_top = self.sen_op.get_top();
         let prev_sens = self.now_status.sen.clone();
         let mut new_op = self.now_status.sen.operation(&self.sen_op.get_top(), &mut |(index, _sen)| {
-            let sen_image: HtmlImageElement = js::dom::get_element_by_id(&format!("bit_{}", index)).unwrap();
-            let rect = sen_image.get_bounding_client_rect();
-
-            // 短角内 は各項目に適用, (On || Off) は全てに適用
-            rect.x() <= x && x < rect.x()+rect.width() && rect.y() <= y && y < rect.y()+rect.height()
+            Some(index) == target
                 || sen_op_top == sen::SenOp::On
                 || sen_op_top == sen::SenOp::Off
         });
 
-        // ドラッグ中の温泉マークを削除
-        if let Some(image) = self.cursor_image.as_mut() {
-            image.remove();
-            self.cursor_image = None;
-        }
-
         // sen に変化があるか
         if prev_sens != self.now_status.sen {
-            // tutorial がついてると削除して非表示にする
-            if let Some(tutorial_cursor) = js::dom::get_element_by_id::<HtmlElement>("tutorial_cursor") {
-                tutorial_cursor.class_list().remove_1("tutorial_senop_cursor").unwrap();
-                tutorial_cursor.class_list().remove_1("tutorial_move_cursor").unwrap();
-            }
+            self.observe_tutorial(tutorial::TutorialEvent::SenChanged {
+                sen: target,
+                op: sen_op_top.to_string().to_lowercase(),
+            });
 
             // SenOp::O だったなら消費する
             if self.sen_op.get_top().is_o() {
@@ -951,27 +1938,28 @@ impl QuastionPage {
         }
 
         // 新しい SenOp があると点滅させる
-        if !new_op.is_empty() {
+        if !new_op.is_empty() && !Resource::user_storage().settings.is_reduced_motion() {
             if let Some(rb_under) = js::dom::get_element_by_id::<HtmlElement>("rb_under") {
                 rb_under.class_list().add_1("blink_new_ruby").unwrap();
             }
         }
 
         let prev_temperature = self.now_status.temperature;
+        self.announcement = self.describe_operation(sen_op_top, &prev_sens, &new_op);
         
         // 各種パラメータの変更: SenOp の追加(あるなら), 操作カウンタ++, 温度再設定, 温泉名へ SenOp の追加
         self.sen_op.append_at_index(&mut new_op);
         self.now_status.ops_count += 1;
         self.now_status.temperature = self.now_status.sen.get_number();
         self.now_status.onsen_name += sen_op_top.to_string().to_lowercase();
+        self.record_statistics(|statistics| statistics.record_op(&sen_op_top.to_string().to_lowercase()));
+        self.announcement += &format!(" 温度 {}℃ → {}℃ (目標 {}℃)", prev_temperature, self.now_status.temperature, self.quastion.temperature);
 
-        // 温度の上下に応じてSEを流す
+        // 温度の上下と目標への近さに応じてSEを流す
         if self.now_status.temperature != self.quastion.temperature {
-            if self.now_status.temperature < prev_temperature {
-                Resource::sound().play(SoundKind::DownTemp);
-            } else if prev_temperature < self.now_status.temperature {
-                Resource::sound().play(SoundKind::UpTemp);
-            }
+            // 線が 16 本以上だと i32 に収まらないので、収まる最大にする
+            let max_temperature = 1i32.checked_shl(2 * self.now_status.sen.len() as u32).map_or(i32::MAX, |limit| limit - 1);
+            Resource::sound().play_temperature(prev_temperature, self.now_status.temperature, self.quastion.temperature, max_temperature);
         }
 
         js::console_log!("{:?} / {:?}", self.sen_op, self.now_status);
@@ -986,6 +1974,9 @@ impl QuastionPage {
     }
 
     fn stage_clear(&mut self) -> Message {
+        // クリアしてもやり直しても、それより前には戻せない
+        self.history.clear();
+
         if self.sen_op.get_top() != sen::SenOp::Off {
             // ☆
             self.now_status.is_clear = true;
@@ -994,7 +1985,35 @@ impl QuastionPage {
 
             // 温泉名の決定
             self.now_status.onsen_name.japanification();
+            // 図鑑で探していればその結果を使い、無ければ結果を表示してから探して珍しさを付ける
+            match Resource::cached_reachable_names(&self.now_status.key) {
+                Some(reachable_names) => self.now_status.onsen_name.rate(reachable_names),
+                None => {
+                    let later = self.later.clone();
+                    js::time::set_timeout(move || later.emit(Message::StageRateOnsenName));
+                },
+            }
+            if let Some(famous) = &self.now_status.onsen_name.famous {
+                js::console_log!("found famous onsen [{}]", famous.kanji);
+            }
+            Resource::user_storage().discover_name(&self.now_status.key, &self.now_status.onsen_name);
+            self.observe_tutorial(tutorial::TutorialEvent::StageCleared);
+            // 手順の途中でもクリアできたならチュートリアルは終わり
+            Resource::user_storage().complete_tutorial(&self.now_status.key);
+            self.tutorial = None;
+            self.announcement += &format!(" クリア！ {}", self.now_status.onsen_name);
+
+            let (ops_count, started_at) = (self.now_status.ops_count, self.started_at);
+            self.record_statistics(|statistics| statistics.record_clear(
+                ops_count,
+                started_at,
+                js::time::now().as_f64().unwrap(),
+            ));
         } else {
+            self.announcement = "やり直し。".to_string();
+            self.record_statistics(|statistics| statistics.record_reset());
+            self.started_at = js::time::now().as_f64().unwrap();
+
             if Resource::user_storage().onsen_status.onsen_status_list[&self.now_status.key].is_cleared() {
                 // 過去にクリアしていると初期化しない
                 self.now_status = Resource::user_storage().onsen_status.onsen_status_list[&self.now_status.key].clone();
@@ -1032,27 +2051,193 @@ impl QuastionPage {
         Message::None
     }
 
-    fn set_tutorial_animation(&self) {
-        if let Some(tutorial_cursor) = js::dom::get_element_by_id::<HtmlElement>("tutorial_cursor") {
-            if !tutorial_cursor.class_list().contains("tutorial_senop_cursor") {
-                // tutorial.1: op の切り替え
-                tutorial_cursor.class_list().remove_1("tutorial_move_cursor").unwrap();
-                tutorial_cursor.class_list().add_1("tutorial_senop_cursor").unwrap();
-            } else {
-                // tutorial.2: 温泉マークへの移動
-                tutorial_cursor.class_list().add_1("tutorial_move_cursor").unwrap();
+    /// SenOp を使った結果の読み上げ文 ("Not: 線2 灰 (0b00) → 灰の反転 (0b11)。" など)
+    fn describe_operation(&self, sen_op: sen::SenOp, prev_sens: &sen::SenManager, new_op: &[sen::SenOp]) -> String {
+        let mut prev = vec![];
+        prev_sens.for_each(|(_index, sen)| prev.push(*sen));
+
+        let mut changes = vec![];
+        self.now_status.sen.for_each(|(index, sen)| {
+            if prev[index] != *sen {
+                changes.push(format!("線{} {} → {}", index + 1, prev[index].describe(Resource::palette()), sen.describe(Resource::palette())));
             }
+        });
+
+        let mut text = format!("{}: {}。", sen_op, if changes.is_empty() { "変化なし".to_string() } else { changes.join("、") });
+        for op in new_op {
+            text += &format!(" {} を得た。", op);
+        }
+
+        text
+    }
+
+    /// 最後に使った SenOp を取り消す
+    fn undo(&mut self) {
+        if let Some((sen_op, now_status)) = self.history.pop() {
+            js::console_log!("undo");
+            self.sen_op = sen_op;
+            self.now_status = now_status;
+            self.announcement = format!("元に戻した。温度 {}℃", self.now_status.temperature);
+        }
+    }
+
+    /// 画面の状態を文章で表す (スクリーンリーダー用)
+    fn get_text_state_html(&self) -> Html {
+        let mut sens = vec![];
+        self.now_status.sen.for_each(|(index, sen)| sens.push(format!("線{}: {}", index + 1, sen.describe(Resource::palette()))));
+
+        html! {
+            <section id="text_state_view" class="visually_hidden" aria-label="お風呂の状態">
+                <p>{ format!("{} 目標 {}℃、今 {}℃", self.quastion.info, self.quastion.temperature, self.now_status.temperature) }</p>
+                <ul>
+                    { for sens.iter().map(|sen| html! { <li>{ sen }</li> }) }
+                </ul>
+                <p>{ format!("演算: {} (前 {}、次 {})", self.sen_op.get_top(), self.sen_op.get_back(), self.sen_op.get_front()) }</p>
+                <p>{ format!("手数: {} (目安 {})", self.now_status.ops_count, self.quastion.ops_border) }</p>
+            </section>
+        }
+    }
+
+    /// キーボードでの操作
+    /// ←↑/→↓: SenOp の切り替え, 1~9: sen を選ぶ, Enter/Space: 適用, z/Backspace: 元に戻す, r: やり直し (Off), Escape: ステージ選択へ
+    fn on_key_down(&mut self, key: &str) -> Message {
+        // 扉が閉じている時はどのキーでも開ける
+        if !self.opened_door {
+            return self.update(Message::OpenDoor);
+        }
+
+        // クリアのポップアップ: Enter: 次, r: 再, Escape: 戻
+        if OnsenStatus::is_show_popup() {
+            return match key {
+                "Enter" | " " => self.update(Message::StageNext),
+                "r" => self.update(Message::StageEnter),
+                "Escape" => self.update(Message::StageBack),
+                _ => Message::None,
+            };
+        }
+
+        match key {
+            "ArrowLeft" | "ArrowUp" => return self.update(Message::PointerDownBackSen),
+            "ArrowRight" | "ArrowDown" => return self.update(Message::PointerDownFrontSen),
+            "Enter" | " " => {
+                let sen_op_top = self.sen_op.get_top();
+                match self.selected_sen {
+                    Some(selected) => return self.apply_sen_op(Some(selected)),
+                    None if sen::SenOp::On == sen_op_top || sen::SenOp::Off == sen_op_top => return self.apply_sen_op(None),
+                    None => self.announcement = "数字キーで線を選んでください。".to_string(),
+                }
+            },
+            "z" | "Backspace" => self.undo(),
+            "r" => {
+                if self.sen_op.select(sen::SenOp::Off) {
+                    return self.apply_sen_op(None);
+                }
+            },
+            "Escape" => return self.update(Message::StageBack),
+            _ => {
+                // 左から 1, 2, ... の sen を選ぶ
+                if let Ok(number) = key.parse::<usize>() {
+                    if 1 <= number && number <= self.now_status.sen.len() {
+                        self.selected_sen = Some(number - 1);
+                        let mut selected = String::new();
+                        self.now_status.sen.for_each(|(index, sen)| if index == number - 1 { selected = sen.describe(Resource::palette()) });
+                        self.announcement = format!("線{} を選択: {}", number, selected);
+                    }
+                }
+            },
+        }
+
+        Message::None
+    }
+
+    /// チュートリアルに出来事を伝える、全ての手順を終えたら保存して二度と表示しない
+    fn observe_tutorial(&mut self, event: tutorial::TutorialEvent) {
+        let runner = match &mut self.tutorial {
+            Some(runner) => runner,
+            None => return,
+        };
+
+        if runner.observe(&event) && runner.is_finished() {
+            js::console_log!("tutorial {} completed", runner.name());
+            Resource::user_storage().complete_tutorial(runner.name());
+            Resource::user_storage().save_data();
+            self.tutorial = None;
+        }
+    }
+
+    const TUTORIAL_CURSOR_CLASSES: [&'static str; 3] = ["tutorial_senop_cursor", "tutorial_move_cursor", "tutorial_drag_cursor"];
+
+    /// 今の手順のカーソルのアニメーションを付ける (同じ手順の間は付け直さない)
+    fn set_tutorial_animation(&self) {
+        let tutorial_cursor = match js::dom::get_element_by_id::<HtmlElement>("tutorial_cursor") {
+            Some(tutorial_cursor) => tutorial_cursor,
+            None => return,
+        };
+
+        let class_name = match self.tutorial.as_ref().and_then(|runner| runner.current()).map(|step| &step.action) {
+            // op の切り替え
+            Some(tutorial::TutorialAction::PointAtOpRuby) => Some("tutorial_senop_cursor"),
+            // 温泉マークを線へ順に
+            Some(tutorial::TutorialAction::DragMarkToSen { sen: None }) => Some("tutorial_move_cursor"),
+            // 温泉マークを指定の線へ
+            Some(tutorial::TutorialAction::DragMarkToSen { sen: Some(sen) }) => {
+                if !tutorial_cursor.class_list().contains("tutorial_drag_cursor") {
+                    Self::set_tutorial_drag_path(&tutorial_cursor, *sen);
+                }
+                Some("tutorial_drag_cursor")
+            },
+            Some(tutorial::TutorialAction::ShowText) | None => None,
+        };
+
+        for name in &Self::TUTORIAL_CURSOR_CLASSES {
+            tutorial_cursor.class_list().toggle_with_force(name, Some(*name) == class_name).unwrap();
+        }
+    }
+
+    /// カーソルが温泉マークから sen 番目 (1 から) の線へ動くように、css の変数に移動量を入れる
+    fn set_tutorial_drag_path(tutorial_cursor: &HtmlElement, sen: usize) {
+        // 動いている途中の位置で測らないように、アニメーションを外してから測る
+        for name in &Self::TUTORIAL_CURSOR_CLASSES {
+            tutorial_cursor.class_list().remove_1(name).unwrap();
+        }
+        let cursor = tutorial_cursor.get_bounding_client_rect();
+        let center_of = |id: &str| js::dom::get_element_by_id::<HtmlElement>(id).map(|element| {
+            let rect = element.get_bounding_client_rect();
+            (rect.left() + rect.width() / 2.0 - cursor.left(), rect.top() + rect.height() / 2.0 - cursor.top())
+        });
+
+        if let (Some((from_x, from_y)), Some((to_x, to_y))) = (center_of("onsen_mark"), center_of(&format!("bit_{}", sen.saturating_sub(1)))) {
+            let style = tutorial_cursor.style();
+            style.set_property("--tutorial_from_x", &format!("{}px", from_x)).unwrap();
+            style.set_property("--tutorial_from_y", &format!("{}px", from_y)).unwrap();
+            style.set_property("--tutorial_to_x", &format!("{}px", to_x)).unwrap();
+            style.set_property("--tutorial_to_y", &format!("{}px", to_y)).unwrap();
+        }
+    }
+
+    /// 今の手順の文章
+    fn get_tutorial_text_html(&self, link: &ComponentLink<MainModel>) -> Html {
+        match self.tutorial.as_ref().and_then(|runner| runner.current()).and_then(|step| step.text.as_ref()) {
+            Some(text) => html! {
+                <div id="tutorial_text" role="status"
+                    onclick=link.callback(|_| Message::TutorialDismiss)
+                >
+                    { text }
+                </div>
+            },
+            None => html! {},
         }
     }
 
+    /// quastions.json でステージに指定された BGM を鳴らす、無ければ sound_manifest.json の BGM
     fn play_bgm(&self) {
-        let bgm_kind = match self.now_status.key.as_str() {
-            "top" => SoundKind::BgmStageTop,
-            _ => SoundKind::BgmStageDefault,
+        let key = &self.quastion.key;
+        let bgm = match &Resource::onsen_status_manager().onsen_status_list[key].bgm {
+            Some(bgm) => bgm.clone(),
+            None => Resource::sound().manifest.stage_bgm(key).to_string(),
         };
 
-        Resource::sound().set_volume(bgm_kind, 0.5);
-        Resource::sound().play_loop(bgm_kind);
+        Resource::sound().play_loop(&bgm);
     }
 }
 impl PageTrait for QuastionPage {
@@ -1065,30 +2250,39 @@ impl PageTrait for QuastionPage {
             <div class="top_container" id="grand_parent_node">
                 <div class="container_item_header">
                     /*{ "banner_area" }*/
+                    <button type="button" id="settings_button"
+                        onclick=link.callback(|_| Message::ChangeToSettingsPage)
+                    >
+                        { "設定" }
+                    </button>
                 </div>
                 <div class="container_item_title">
                     <div id="title_text" class="normal_font">
                         <div>
                             <ruby>
-                                <div
+                                <button type="button" class="ruby_op_button"
                                     data-back-ruby={ self.sen_op.get_back() }
-                                    ontouchstart=link.callback(|_| Message::TouchStartBackSen)
-                                ></div>
-                                <rb ontouchstart=link.callback(|_| Message::TouchStartFrontSen)>
+                                    aria-label={ format!("前の演算 {}", self.sen_op.get_back()) }
+                                    onclick=link.callback(|_| Message::PointerDownBackSen)
+                                ></button>
+                                <rb onpointerdown=link.callback(|_| Message::PointerDownFrontSen)>
                                     { self.sen_op.get_top() }
                                 </rb>
-                                <div
+                                <button type="button" class="ruby_op_button"
                                     id="rb_under"
                                     data-front-ruby={ self.sen_op.get_front() }
-                                    ontouchstart=link.callback(|_| Message::TouchStartFrontSen)
+                                    aria-label={ format!("次の演算 {}", self.sen_op.get_front()) }
+                                    onclick=link.callback(|_| Message::PointerDownFrontSen)
                                     onanimationend=link.callback(|_| Message::BlinkAnimationEnd)
-                                ></div>
+                                ></button>
                             </ruby>
                             { "Sen" }
-                            <img id="onsen_mark" src={ self.sen_op.get_top().to_file_name() } alt="onsen_mark"
-                                ontouchstart=link.callback(|event| Message::TouchStart(event))
-                                ontouchmove=link.callback(|event| Message::TouchMove(event))
-                                ontouchend=link.callback(|_| Message::TouchEnd)
+                            <img id="onsen_mark" src={ Resource::mark_path(&self.sen_op.get_top().to_file_name()) } draggable="false"
+                                alt={ format!("温泉マーク: {}Sen (線に重ねて使う)", self.sen_op.get_top()) }
+                                onpointerdown=link.callback(|event| Message::PointerDown(event))
+                                onpointermove=link.callback(|event| Message::PointerMove(event))
+                                onpointerup=link.callback(|event| Message::PointerUp(event))
+                                onpointercancel=link.callback(|_| Message::PointerCancel)
                                 />
                         </div>
                     </div>
@@ -1098,21 +2292,30 @@ impl PageTrait for QuastionPage {
                     /*{ "banner_area" }*/
                 </div>
                 <img id="left_door" src="/resource/image/left_door.png" alt="left_door"
-                    ontouchend=link.callback(|event| Message::OpenDoor)
+                    onclick=link.callback(|event| Message::OpenDoor)
                     onanimationend=link.callback(|event| Message::OpenedDoor) />
                 <img id="right_door" src="/resource/image/right_door.png" alt="right_door"
-                    ontouchend=link.callback(|event| Message::OpenDoor) />
+                    onclick=link.callback(|event| Message::OpenDoor) />
             </div>
         }
     }
 
     fn update(&mut self, message: Message) -> Message {
         match message {
-            Message::TouchStart(event) => {
+            Message::PointerDown(event) => {
+                // 二本目の指などで、ドラッグ中に別のドラッグを始めない
+                if self.drag.is_some() || !event.is_primary() {
+                    return Message::None;
+                }
+                self.prepare_hit_masks();
+
                 // ドラッグ中の温泉マークを作成
-                let x = event.touches().get(0).unwrap().client_x();
-                let y = event.touches().get(0).unwrap().client_y();
+                let x = event.client_x();
+                let y = event.client_y();
+                self.drag = Some(drag::DragController::start(event.pointer_id(), x as f64, y as f64));
                 let parent_image: HtmlImageElement = js::dom::get_element_by_id("onsen_mark").unwrap();
+                // 温泉マークの外へ出ても pointermove, pointerup を受け取る
+                let _ = parent_image.set_pointer_capture(event.pointer_id());
                 
                 let image: HtmlImageElement = js::dom::make_img_element( &parent_image.current_src() );
                 image.set_id("temp_onsen_mark");
@@ -1123,17 +2326,35 @@ impl PageTrait for QuastionPage {
                 js::dom::get_element_by_id::<HtmlElement>("grand_parent_node").unwrap()
                     .append_child(self.cursor_image.as_ref().unwrap()).unwrap();
             },
-            Message::TouchMove(event) => {
-                // ドラッグ中の温泉マークの座標をカーソルに追尾させる
-                let x = event.touches().get(0).unwrap().client_x();
-                let y = event.touches().get(0).unwrap().client_y();
-                if let Some(image) = &self.cursor_image {
+            Message::PointerMove(event) => {
+                // ドラッグ中の温泉マークの座標をカーソルに追尾させる (マウスはボタンを押していなくても届く)
+                let x = event.client_x();
+                let y = event.client_y();
+                let is_dragging = self.drag.as_mut().is_some_and(|drag| drag.move_to(event.pointer_id(), x as f64, y as f64));
+                if let (true, Some(image)) = (is_dragging, &self.cursor_image) {
                     image.style().set_property("left", &format!("{}px", x) ).unwrap();
                     image.style().set_property("top", &format!("{}px", y) ).unwrap();
                 }
             },
-            Message::TouchEnd => {
-                return self.moved_onsen_mark();
+            Message::KeyDown(key) => {
+                return self.on_key_down(&key);
+            },
+            Message::PointerUp(event) => {
+                // ドラッグしていない指が離れても、ドラッグは続ける
+                if let Some(mut drag) = self.drag {
+                    if drag.move_to(event.pointer_id(), event.client_x() as f64, event.client_y() as f64) {
+                        self.drag = None;
+                        let (x, y) = drag.position();
+                        return self.moved_onsen_mark(x, y);
+                    }
+                }
+            },
+            Message::PointerCancel => {
+                // スクロールなどでドラッグが中断されたら、何もせずに温泉マークを消す
+                self.drag = None;
+                if let Some(image) = self.cursor_image.take() {
+                    image.remove();
+                }
             },
             Message::StageEnter => {
                 OnsenStatus::hide_popup();
@@ -1148,17 +2369,26 @@ impl PageTrait for QuastionPage {
             Message::StageNext => {
                 OnsenStatus::hide_popup();
 
+                // 次のステージが開いていなければ、足りないものが分かるようにステージ選択に戻る
+                if !OnsenStatus::get_onsen_status_from_name(&self.now_status.next_key()).missing_requirements().is_empty() {
+                    return Message::ChangeToLockedStage(self.now_status.next_key());
+                }
                 return Message::ChangeToQuastionPage(
                     self.now_status.next_key()
                 );
             },
-            Message::TouchStartBackSen => {
-                self.sen_op.prev()
+            Message::PointerDownBackSen | Message::PointerDownFrontSen => {
+                if let Message::PointerDownBackSen = message {
+                    self.sen_op.prev();
+                } else {
+                    self.sen_op.next();
+                }
+                self.announcement = format!("演算: {}", self.sen_op.get_top());
+                self.observe_tutorial(tutorial::TutorialEvent::OpChanged { op: self.sen_op.get_top().to_string().to_lowercase() });
+            },
+            Message::TutorialDismiss => {
+                self.observe_tutorial(tutorial::TutorialEvent::Dismissed);
             },
-            Message::TouchStartFrontSen => {
-                self.sen_op.next();
-                self.set_tutorial_animation();
-        },
             Message::BlinkAnimationEnd => {
                 if let Some(rb_under) = js::dom::get_element_by_id::<HtmlElement>("rb_under") {
                     rb_under.class_list().remove_1("blink_new_ruby").unwrap();
@@ -1169,6 +2399,8 @@ impl PageTrait for QuastionPage {
                     return Message::None;
                 }
                 self.opened_door = true;
+                // 再/次 で扉が開いたままのページも挑戦として数えるが、ヒントからのリダイレクトで開き直したページは数えない
+                let is_hint_redirect = self.first_page && js::dom::get_param(Self::POPUP_PARAM_NAME).is_some();
                 self.first_page = false;
 
                 let mut is_retry = false;
@@ -1184,22 +2416,48 @@ impl PageTrait for QuastionPage {
                     right_door.class_list().add_1("open_right_door").unwrap();
                 }
 
+                if !is_hint_redirect {
+                    self.record_attempt();
+                }
+
                 if is_retry {
                     js::console_log!("retry. opened door");
                     self.play_bgm();
                 } else {
                     js::console_log!("open door");
                     Resource::sound().play(SoundKind::StageEnter);
+
+                    // アニメーションしないと animationend が来ないので、開き終わった時の処理をすぐに行う
+                    if Resource::user_storage().settings.is_reduced_motion() {
+                        return self.update(Message::OpenedDoor);
+                    }
                 }
             },
             Message::OpenedDoor => {
                 self.play_bgm();
             },
+            Message::StageRateOnsenName => {
+                // 探している間に 次 で別のステージへ移っていれば、珍しさを付ける温泉名は無い
+                if !self.now_status.is_cleared() || self.now_status.onsen_name.rarity.is_some() {
+                    return Message::None;
+                }
+                self.now_status.onsen_name.rate(Resource::reachable_names(&self.now_status.key));
+
+                // 保存した温泉名と同じなら、珍しさも保存する
+                let saved_status = Resource::user_storage().onsen_status.onsen_status_list.get_mut(&self.now_status.key).unwrap();
+                if saved_status.onsen_name.roma == self.now_status.onsen_name.roma && saved_status.onsen_name.rarity.is_none() {
+                    saved_status.onsen_name.rarity = self.now_status.onsen_name.rarity;
+                    Resource::user_storage().save_data();
+                }
+            },
             Message::StageTweet | Message::StageYoutube => {
                 Resource::user_storage().init_onsen_key = self.now_status.key.to_string();
                 Resource::user_storage().save_data();
                 self.now_status.use_hint_and_save();
             },
+            Message::ChangeToSettingsPage => {
+                return Message::ChangeToSettingsPage;
+            },
             _ => (),
         }
 
@@ -1209,69 +2467,536 @@ impl PageTrait for QuastionPage {
     fn rendered(&mut self, first_render: bool) {
         self.now_status.rendered();
 
+        if self.is_reopening_door {
+            self.is_reopening_door = false;
+            for (id, class_name) in &[("left_door", "open_left_door"), ("right_door", "open_right_door")] {
+                if let Some(door) = js::dom::get_element_by_id::<HtmlElement>(id) {
+                    door.class_list().add_1(class_name).unwrap();
+                }
+            }
+        }
+
         // onsen_mark_sen に動的にぶら下げるので sen はここで表示
         let onsen_mark_sen: HtmlElement = js::dom::get_element_by_id("onsen_mark_sen").unwrap();
         self.now_status.sen.for_each(|(index, sen)|{
             // 存在すれば上書き、なければ作成
+            let label = format!("線{}: {}", index + 1, sen.describe(Resource::palette()));
             match js::dom::get_element_by_id::<HtmlImageElement>( &format!("bit_{}", index) ) {
                 Some(sen_image) => {
-                    sen_image.set_src( &sen.to_file_name(index) );
+                    sen_image.set_src( &Resource::mark_path(&sen.to_file_name(index)) );
+                    sen_image.set_alt(&label);
                 },
                 None => {
-                    let sen_image = js::dom::make_img_element( &sen.to_file_name(index) );
+                    let sen_image = js::dom::make_img_element( &Resource::mark_path(&sen.to_file_name(index)) );
                     sen_image.set_id( &format!("bit_{}", index) );
+                    sen_image.set_alt(&label);
                     onsen_mark_sen.append_child(&sen_image).unwrap();
                 },
             }
         });
 
+        // キーボードで選んでいる sen を目立たせる
+        for index in 0..self.now_status.sen.len() {
+            if let Some(sen_image) = js::dom::get_element_by_id::<HtmlElement>(&format!("bit_{}", index)) {
+                let _ = sen_image.class_list().toggle_with_force("keyboard_selected_sen", Some(index) == self.selected_sen);
+            }
+        }
+
+        // チュートリアルの手順に合わせてカーソルを動かす
+        self.set_tutorial_animation();
+
         if first_render {
             if self.first_page && self.now_status.use_hint {
                 if let Some(_) = js::dom::get_param(Self::POPUP_PARAM_NAME) {
                     OnsenStatus::show_popup();
                 }
             }
+        }
+    }
+
+    fn resumed(&mut self) {
+        // 扉は作り直されて閉じているので、開いていたなら音を鳴らさずに開け直す
+        self.is_reopening_door = self.opened_door;
+    }
+}
+
+/// ステージ毎のプレイ統計を表示するページ
+struct StatisticsPage {
+}
+impl StatisticsPage {
+    fn new() -> Self {
+        Self {
+        }
+    }
+
+    fn get_row_html(key: &str, status: &OnsenStatus) -> Html {
+        let stat = Resource::user_storage().statistics(key).clone();
+        let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+
+        html! {
+            <tr>
+                <td>{ key.trim_start_matches("stage_") }</td>
+                <td>{ &status.info }</td>
+                <td>{ stat.attempts }</td>
+                <td>{ stat.clears }{ or_dash(stat.clear_rate().map(|rate| format!(" ({:.0}%)", rate * 100.0))) }</td>
+                <td>{ stat.resets }</td>
+                <td>{ or_dash(stat.best_ops_count.map(|count| format!("{}/{}", count, status.ops_border))) }</td>
+                <td>{ or_dash(stat.best_clear_time.map(statistics::StageStatistics::format_duration)) }</td>
+                <td>{ stat.hints_used }</td>
+                <td>{ or_dash(stat.favorite_op().map(|op_name| op_name.to_string())) }</td>
+                <td>{ or_dash(stat.first_clear_time.map(js::time::format_date)) }</td>
+            </tr>
+        }
+    }
+}
+impl PageTrait for StatisticsPage {
+    fn view(&self, link: &ComponentLink<MainModel>) -> Html {
+        let keys = Resource::onsen_status_manager().stage_keys();
+
+        html! {
+            <div class="statistics_container" id="grand_parent_node">
+                <div class="container_item_header">
+                    <button type="button" id="statistics_close_button"
+                        onclick=link.callback(|_| Message::StatisticsClose)
+                    >
+                        { "戻" }
+                    </button>
+                </div>
+                <div class="statistics_container_item_content">
+                    <table id="statistics_table">
+                        <thead>
+                            <tr>
+                                <th>{ "面" }</th>
+                                <th>{ "お題" }</th>
+                                <th>{ "挑戦" }</th>
+                                <th>{ "クリア" }</th>
+                                <th>{ "Off" }</th>
+                                <th>{ "最少手" }</th>
+                                <th>{ "最短" }</th>
+                                <th>{ "ヒント" }</th>
+                                <th>{ "よく使う" }</th>
+                                <th>{ "初クリア" }</th>
+                            </tr>
+                        </thead>
+                        <tbody>
+                            { for keys.iter().map(|key| Self::get_row_html(key, OnsenStatus::get_onsen_status_from_name(key))) }
+                        </tbody>
+                    </table>
+                </div>
+            </div>
+        }
+    }
+
+    fn update(&mut self, message: Message) -> Message {
+        match message {
+            Message::StatisticsClose => Message::ChangeToSelectPage,
+            Message::KeyDown(key) if "Escape" == key => Message::ChangeToSelectPage,
+            _ => Message::None,
+        }
+    }
+}
+
+/// 見つけた温泉名の図鑑のページ
+/// 見つけられる温泉名は探すのに時間がかかるので、描画の後に一ステージずつ探して未発見の数を埋めていく
+struct GalleryPage {
+    later: yew::Callback<Message>,  // 描画の後で探すためのメッセージを送る
+    is_search_scheduled: bool,      // 次のステージを探すメッセージを送ってあるか
+}
+impl GalleryPage {
+    fn new(later: &yew::Callback<Message>) -> Self {
+        Self {
+            later: later.clone(),
+            is_search_scheduled: false,
+        }
+    }
+
+    /// まだ見つけられる温泉名を探していないステージ
+    fn next_unsearched_key(&self) -> Option<&'static String> {
+        Resource::onsen_status_manager().stage_keys().into_iter()
+            .find(|key| Resource::cached_reachable_names(key).is_none())
+    }
+
+    fn get_stage_html(&self, key: &str) -> Html {
+        let discovered: Vec<&DiscoveredName> = Resource::user_storage().discovered_names.iter()
+            .filter(|discovered| discovered.stage == key)
+            .collect();
+        // 探し終えるまでは数えられない
+        let undiscovered_count = match Resource::cached_reachable_names(key) {
+            Some(reachable_names) => {
+                let count = reachable_names.counts.keys().filter(|name| !discovered.iter().any(|discovered| &discovered.name == *name)).count();
+                // 探索しきれていなければ、もっとあるかもしれない
+                format!("{}{}", count, if reachable_names.is_complete { "" } else { "+" })
+            },
+            None => "…".to_string(),
+        };
 
-            // 初回 かつ 未クリア時 で 最初の方のステージ でのみチュートリアル用のカーソルアニメーションを表示
-            if !self.now_status.is_cleared() {
-                if "top" == self.now_status.key {
-                    self.set_tutorial_animation();
+        html! {
+            <div class="gallery_stage">
+                <div class="gallery_stage_header">
+                    <span>{ key.trim_start_matches("stage_") }{ " " }{ &OnsenStatus::get_onsen_status_from_name(key).info }</span>
+                    <span>{ format!("発見 {} / 未発見 {}", discovered.len(), undiscovered_count) }</span>
+                </div>
+                <ul class="gallery_name_list">
+                    { for discovered.iter().map(|discovered| html! {
+                        <li>
+                            <span class="gallery_name">{ &discovered.name }</span>
+                            <span class="gallery_ops">{ discovered.ops.join(" → ") }</span>
+                            <span class="gallery_date">{ js::time::format_date(discovered.discovered_at) }</span>
+                        </li>
+                    }) }
+                </ul>
+            </div>
+        }
+    }
+}
+impl PageTrait for GalleryPage {
+    fn view(&self, link: &ComponentLink<MainModel>) -> Html {
+        html! {
+            <div class="gallery_container" id="grand_parent_node">
+                <div class="container_item_header">
+                    <button type="button" id="gallery_close_button"
+                        onclick=link.callback(|_| Message::GalleryClose)
+                    >
+                        { "戻" }
+                    </button>
+                </div>
+                <div class="gallery_container_item_content">
+                    { for Resource::onsen_status_manager().stage_keys().iter().map(|key| self.get_stage_html(key)) }
+                </div>
+            </div>
+        }
+    }
+
+    fn update(&mut self, message: Message) -> Message {
+        match message {
+            Message::GalleryClose => Message::ChangeToSelectPage,
+            Message::GallerySearchReachableNames => {
+                self.is_search_scheduled = false;
+                if let Some(key) = self.next_unsearched_key() {
+                    Resource::reachable_names(key);
                 }
-            }
+                Message::None
+            },
+            Message::KeyDown(key) if "Escape" == key => Message::ChangeToSelectPage,
+            _ => Message::None,
+        }
+    }
+
+    fn rendered(&mut self, _first_render: bool) {
+        // 一ステージ探す毎に描画し直して、探し終えたステージから未発見の数を表示する
+        if !self.is_search_scheduled && self.next_unsearched_key().is_some() {
+            self.is_search_scheduled = true;
+            let later = self.later.clone();
+            js::time::set_timeout(move || later.emit(Message::GallerySearchReachableNames));
         }
     }
 }
 
-struct PageManager {
-    page: Box<dyn PageTrait>,
+/// 設定のページ (ステージ選択と問題のページから開き、閉じると開いたページに戻る)
+struct SettingsPage {
+    notice: Option<String>, // 直前の操作の結果
+    is_progress_reset: bool,    // 進行状況を消したので、開いたページには戻らない
 }
-impl PageManager {
+impl SettingsPage {
     fn new() -> Self {
         Self {
-            page: Box::new(QuastionPage::new_from_localstorage()),
+            notice: None,
+            is_progress_reset: false,
+        }
+    }
+
+    /// チェックボックスを返す
+    fn get_checkbox_html(link: &ComponentLink<MainModel>, label: &str, checked: bool, message: Message) -> Html {
+        html! {
+            <label class="settings_item">
+                <span>{ label }</span>
+                <input type="checkbox" checked=checked
+                    onclick=link.callback(move |_| message.clone())
+                />
+            </label>
         }
     }
 
+    /// 音量のスライダー (0 ~ 100) を返す
+    fn get_volume_html(link: &ComponentLink<MainModel>, label: &str, volume: f64, message: fn(f64) -> Message) -> Html {
+        html! {
+            <label class="settings_item">
+                <span>{ label }</span>
+                <input type="range" min="0" max="100" value={ (volume * 100.0).round().to_string() }
+                    onchange=link.callback(move |change: ChangeData| match change {
+                        ChangeData::Value(value) => message(value.parse::<f64>().unwrap_or(0.0) / 100.0),
+                        _ => Message::None,
+                    })
+                />
+            </label>
+        }
+    }
+
+    fn get_audio_html(link: &ComponentLink<MainModel>) -> Html {
+        let audio_settings = Resource::user_storage().settings.audio;
+
+        html! {
+            <section>
+                <h2>{ "音" }</h2>
+                { Self::get_checkbox_html(link, "消音", audio_settings.is_muted, Message::SettingsMuteToggle) }
+                { Self::get_volume_html(link, "全体", audio_settings.master_volume, Message::SettingsMasterVolume) }
+                { Self::get_volume_html(link, "BGM", audio_settings.bgm_volume, Message::SettingsBgmVolume) }
+                { Self::get_volume_html(link, "効果音", audio_settings.se_volume, Message::SettingsSeVolume) }
+            </section>
+        }
+    }
+
+    fn get_theme_html(link: &ComponentLink<MainModel>) -> Html {
+        let theme_settings = Resource::user_storage().settings.theme;
+
+        html! {
+            <section>
+                <h2>{ "見た目" }</h2>
+                <label class="settings_item">
+                    <span>{ "配色" }</span>
+                    <select
+                        onchange=link.callback(|change: ChangeData| match change {
+                            ChangeData::Select(select) => settings::Palette::from_id(&select.value()).map_or(Message::None, Message::SettingsPalette),
+                            _ => Message::None,
+                        })
+                    >
+                        { for settings::Palette::ALL.iter().map(|palette| html! {
+                            <option value={ palette.id() } selected={ *palette == theme_settings.palette }>{ palette.label() }</option>
+                        }) }
+                    </select>
+                </label>
+                { Self::get_checkbox_html(link, "線に模様を付ける", theme_settings.pattern_overlay, Message::SettingsPatternToggle) }
+                <img id="settings_theme_preview" alt="配色の見本"
+                    src={ theme_settings.mark_path(&sen::SenOp::OrO(sen::Sen::new(0b01)).to_file_name()) }
+                />
+                { Self::get_checkbox_html(link, "動きを減らす", Resource::user_storage().settings.is_reduced_motion(), Message::SettingsReducedMotionToggle) }
+            </section>
+        }
+    }
+
+    fn get_language_html(link: &ComponentLink<MainModel>) -> Html {
+        let locale = Resource::user_storage().settings.locale.clone();
+        let name_forms = Resource::user_storage().settings.name_forms();
+
+        html! {
+            <section>
+                <h2>{ "言語" }</h2>
+                <label class="settings_item">
+                    <span>{ "言語" }</span>
+                    <select
+                        onchange=link.callback(|change: ChangeData| match change {
+                            ChangeData::Select(select) => Message::SettingsLocale(select.value()),
+                            _ => Message::None,
+                        })
+                    >
+                        { for settings::Settings::LOCALES.iter().map(|(id, label)| html! {
+                            <option value={ *id } selected={ *id == locale }>{ label }</option>
+                        }) }
+                    </select>
+                </label>
+                { Self::get_checkbox_html(link, "温泉名のローマ字", name_forms.romanized, Message::SettingsRomanizedToggle) }
+                { Self::get_checkbox_html(link, "温泉名の英語", name_forms.english, Message::SettingsEnglishToggle) }
+            </section>
+        }
+    }
+
+    fn get_data_html(&self, link: &ComponentLink<MainModel>) -> Html {
+        html! {
+            <section>
+                <h2>{ "データ" }</h2>
+                <div class="settings_item">
+                    <button type="button" onclick=link.callback(|_| Message::SettingsResetTutorial)>{ "チュートリアルをもう一度" }</button>
+                </div>
+                <div class="settings_item">
+                    <button type="button" onclick=link.callback(|_| Message::SettingsResetProgress)>{ "進行状況を消す" }</button>
+                </div>
+                <div class="settings_item">
+                    <button type="button" onclick=link.callback(|_| Message::SettingsClearCache)>{ "キャッシュを消す" }</button>
+                </div>
+                <p id="settings_notice" role="status" aria-live="polite">{ self.notice.clone().unwrap_or_default() }</p>
+            </section>
+        }
+    }
+
+    /// 設定を変えて保存する (画像や温泉名は描画の度に設定から作るので、反映は再描画で行われる)
+    fn update_settings<F: FnOnce(&mut settings::Settings)>(f: F) {
+        f(&mut Resource::user_storage().settings);
+        Resource::user_storage().save_data();
+    }
+
+    /// 閉じる時のメッセージ
+    fn close(&self) -> Message {
+        if self.is_progress_reset {
+            Message::ChangeToSelectPage
+        } else {
+            Message::SettingsClose
+        }
+    }
+
+    /// 音量の設定を変えて、保存して反映する
+    fn update_audio<F: FnOnce(&mut settings::AudioSettings)>(f: F) {
+        let audio_settings = &mut Resource::user_storage().settings.audio;
+        f(audio_settings);
+        Resource::sound().apply_settings(audio_settings);
+        Resource::user_storage().save_data();
+    }
+}
+impl PageTrait for SettingsPage {
+    fn view(&self, link: &ComponentLink<MainModel>) -> Html {
+        html! {
+            <div class="settings_container" id="grand_parent_node">
+                <div class="container_item_header">
+                    <button type="button" id="settings_close_button"
+                        onclick=link.callback(|_| Message::SettingsClose)
+                    >
+                        { "戻" }
+                    </button>
+                </div>
+                <div class="settings_container_item_content">
+                    { Self::get_audio_html(link) }
+                    { Self::get_theme_html(link) }
+                    { Self::get_language_html(link) }
+                    { self.get_data_html(link) }
+                </div>
+            </div>
+        }
+    }
+
+    fn update(&mut self, message: Message) -> Message {
+        match message {
+            Message::SettingsMasterVolume(volume) => Self::update_audio(|audio| audio.master_volume = volume),
+            Message::SettingsBgmVolume(volume) => Self::update_audio(|audio| audio.bgm_volume = volume),
+            Message::SettingsSeVolume(volume) => Self::update_audio(|audio| audio.se_volume = volume),
+            Message::SettingsMuteToggle => Self::update_audio(|audio| audio.is_muted = !audio.is_muted),
+            Message::SettingsPalette(palette) => Self::update_settings(|settings| settings.theme.palette = palette),
+            Message::SettingsPatternToggle => Self::update_settings(|settings| settings.theme.pattern_overlay = !settings.theme.pattern_overlay),
+            Message::SettingsReducedMotionToggle => Self::update_settings(|settings| settings.reduced_motion = Some(!settings.is_reduced_motion())),
+            Message::SettingsLocale(locale) => Self::update_settings(|settings| settings.locale = locale),
+            Message::SettingsRomanizedToggle => Self::update_settings(|settings| {
+                let mut name_forms = settings.name_forms();
+                name_forms.romanized = !name_forms.romanized;
+                settings.set_name_forms(name_forms);
+            }),
+            Message::SettingsEnglishToggle => Self::update_settings(|settings| {
+                let mut name_forms = settings.name_forms();
+                name_forms.english = !name_forms.english;
+                settings.set_name_forms(name_forms);
+            }),
+            Message::SettingsResetTutorial => {
+                Resource::user_storage().reset_tutorials();
+                Resource::user_storage().save_data();
+                self.notice = Some("次にステージを開くとチュートリアルが表示されます。".to_string());
+            },
+            Message::SettingsResetProgress => {
+                if !js::dom::confirm("クリア情報、統計、図鑑をすべて消します。よろしいですか？") {
+                    return Message::None;
+                }
+                js::console_log!("reset progress");
+                Resource::user_storage().reset_progress();
+                Resource::user_storage().save_data();
+                self.is_progress_reset = true;
+                self.notice = Some("進行状況を消しました。".to_string());
+            },
+            Message::SettingsClearCache => {
+                js::delete_all_cache();
+                self.notice = Some("キャッシュを消しました。次に開いた時に読み込み直します。".to_string());
+            },
+            Message::SettingsClose => return self.close(),
+            Message::KeyDown(key) if "Escape" == key => return self.close(),
+            _ => (),
+        }
+
+        Message::None
+    }
+}
+
+struct PageManager {
+    page: Box<dyn PageTrait>,
+    suspended_page: Option<Box<dyn PageTrait>>,    // 設定のページを閉じた時に戻るページ
+    later: yew::Callback<Message>,                  // 描画の後で時間のかかる処理をするためのメッセージを、ページへ送る
+    #[cfg(feature = "sync")]
+    synchronized: yew::Callback<()>,                // 同期先と合わせ終えたら描画し直す
+}
+impl PageManager {
+    fn new(link: &ComponentLink<MainModel>) -> Self {
+        let later = link.callback(|message| message);
+        let page_manager = Self {
+            page: Box::new(QuastionPage::new_from_localstorage(&later)),
+            suspended_page: None,
+            later,
+            #[cfg(feature = "sync")]
+            synchronized: link.callback(|_| Message::Synchronized),
+        };
+
+        #[cfg(feature = "sync")]
+        Resource::user_storage().synchronize(page_manager.synchronized.clone());
+
+        page_manager
+    }
+
     fn update(&mut self, message: Message) -> ShouldRender {
+        if let Message::StorageChanged(key, Some(new_value)) = &message {
+            if key == UserStorage::STORAGE_DATA_NAME {
+                js::console_log!("synchronized {} from other tab", key);
+                Resource::user_storage().on_storage_changed(new_value);
+            }
+        }
+
         match self.page.as_mut().update(message.clone()) {
             Message::ChangeToQuastionPage(name) => {
                 Resource::user_storage().init_onsen_key = name;
                 Resource::user_storage().save_data();
-                Resource::sound().pause_all();
+                // ステージの BGM は扉が開いてから鳴らすので、先に止めておく
+                Resource::sound().fade_out_bgm();
 
-                self.page = Box::new(QuastionPage::new(&Resource::user_storage().init_onsen_key));
+                self.page = Box::new(QuastionPage::new(&Resource::user_storage().init_onsen_key, &self.later));
             },
-            Message::ChangeToSelectPage => {
-                Resource::sound().pause_all();
-
-                self.page = Box::new(SelectPage::new());
+            message @ (Message::ChangeToSelectPage | Message::ChangeToLockedStage(_)) => {
+                self.suspended_page = None;
+                // BGM は SelectPage::new で切り替える (統計などから戻ってきた時は同じ曲なので鳴らし続ける)
+                // ステージから戻ってきた時に同期する
+                #[cfg(feature = "sync")]
+                Resource::user_storage().synchronize(self.synchronized.clone());
+
+                let mut select_page = SelectPage::new();
+                if let Message::ChangeToLockedStage(key) = message {
+                    select_page.show_locked_stage(&key);
+                }
+                self.page = Box::new(select_page);
+            },
+            Message::ChangeToStatisticsPage => {
+                self.page = Box::new(StatisticsPage::new());
+            },
+            Message::ChangeToGalleryPage => {
+                self.page = Box::new(GalleryPage::new(&self.later));
+            },
+            Message::ChangeToSettingsPage => {
+                // 開いたページは、閉じた時にそのまま戻れるように取っておく
+                self.suspended_page = Some(std::mem::replace(&mut self.page, Box::new(SettingsPage::new())));
+            },
+            Message::SettingsClose => {
+                match self.suspended_page.take() {
+                    Some(page) => {
+                        self.page = page;
+                        self.page.as_mut().resumed();
+                    },
+                    None => self.page = Box::new(SelectPage::new()),
+                }
             },
             _ => (),
         }
 
         match message {
             Message::StageBack | Message::StageClose | Message::StageEnter | Message::StageNext
-            | Message::StageSelect(_) | Message::TouchStartBackSen | Message::TouchStartFrontSen => {
+            | Message::StageSelect(_) | Message::SelectLevel(_) | Message::PointerDownBackSen | Message::PointerDownFrontSen
+            | Message::ChangeToStatisticsPage | Message::StatisticsClose
+            | Message::ChangeToGalleryPage | Message::GalleryClose
+            | Message::ChangeToSettingsPage | Message::SettingsClose
+            | Message::SettingsSeVolume(_) | Message::SettingsMuteToggle
+            | Message::SettingsPalette(_) | Message::SettingsPatternToggle
+            | Message::SettingsLocale(_) | Message::SettingsRomanizedToggle | Message::SettingsEnglishToggle
+            | Message::SettingsReducedMotionToggle | Message::SettingsResetTutorial
+            | Message::SettingsResetProgress | Message::SettingsClearCache => {
                 Resource::sound().play(SoundKind::ButtonUp);
             },
             _ => (),
@@ -1282,9 +3007,8 @@ impl PageManager {
 
     fn view(&self, link: &ComponentLink<MainModel>) -> Html {
         html! {
-            <div id="base_view">
+            <div id="base_view" class={ if Resource::user_storage().settings.is_reduced_motion() { "reduced_motion" } else { "" } }>
                 { self.page.as_ref().view(link) }
-                { Resource::sound().view(link) }
             </div>
         }
     }
@@ -1293,12 +3017,11 @@ impl PageManager {
     // 描画後
     fn rendered(&mut self, first_render: bool) {
         if let Some(stage_name) = js::dom::get_element_by_id::<HtmlElement>("stage_name") {
-            if Self::TEXT_LARGE_LIMIT < stage_name.text_content().unwrap().len() {
-                // 長すぎると流れるアニメーションを付与する
-                stage_name.class_list().add_1("stage_name_flowing").unwrap();
-            } else {
-                stage_name.class_list().remove_1("stage_name_flowing").unwrap();
-            }
+            let is_large = Self::TEXT_LARGE_LIMIT < stage_name.text_content().unwrap().len();
+            let is_reduced_motion = Resource::user_storage().settings.is_reduced_motion();
+            // 長すぎると流れるアニメーションを付与する、アニメーションを減らすなら折り返して収める
+            stage_name.class_list().toggle_with_force("stage_name_flowing", is_large && !is_reduced_motion).unwrap();
+            stage_name.class_list().toggle_with_force("stage_name_fitted", is_large && is_reduced_motion).unwrap();
         }
 
         self.page.as_mut().rendered(first_render);
@@ -1316,9 +3039,23 @@ impl Component for MainModel {
 
     // 作成
     fn create(_: Self::Properties, link: ComponentLink<Self>) -> Self {
+        // 別タブでの保存を受け取る
+        let storage_changed = link.callback(|(key, new_value)| Message::StorageChanged(key, new_value));
+        storage::add_storage_listener(move |key, new_value| storage_changed.emit((key, new_value)));
+
+        // キーボードでの操作を受け取る
+        let key_down = link.callback(Message::KeyDown);
+        js::dom::add_key_listener(move |key| key_down.emit(key));
+
+        // 配色や模様の画像が無ければ、標準の画像で表示する
+        js::dom::add_image_error_listener(settings::ThemeSettings::standard_mark_path);
+
+        // 最初の音を鳴らすまでに読み込んでおく
+        Resource::sound();
+
         Self {
+            page_manager: PageManager::new(&link),
             link,
-            page_manager: PageManager::new(),
         }
     }
 
@@ -1342,3 +3079,174 @@ impl Component for MainModel {
         self.page_manager.rendered(first_render);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(key: &str, ops_count: i32, roma: &str) -> OnsenStatus {
+        OnsenStatus {
+            is_clear: true,
+            ops_count,
+            onsen_name: OnsenName {
+                name: roma.to_string(),
+                roma: roma.to_string(),
+                ..OnsenName::default()
+            },
+            key: key.to_string(),
+            ..OnsenStatus::default()
+        }
+    }
+
+    /// a に b を合わせたものと、b に a を合わせたもの
+    fn merged_both_ways(a: &OnsenStatus, b: &OnsenStatus) -> (serde_json::Value, serde_json::Value) {
+        let mut ab = a.clone();
+        ab.merge(b);
+        let mut ba = b.clone();
+        ba.merge(a);
+
+        (serde_json::to_value(&ab).unwrap(), serde_json::to_value(&ba).unwrap())
+    }
+
+    /// Settings::default は navigator を見るので、ネイティブでは JSON から作る
+    fn user_storage(statuses: &[OnsenStatus]) -> UserStorage {
+        let mut user_storage: UserStorage = serde_json::from_value(serde_json::json!({
+            "init_onsen_key": "top",
+            "init_stage_level": 0,
+            "onsen_status": { "level_list": [0], "onsen_status_list": {} },
+            "settings": { "locale": "ja" },
+        })).unwrap();
+        for status in statuses {
+            user_storage.onsen_status.onsen_status_list.insert(status.key.clone(), status.clone());
+        }
+
+        user_storage
+    }
+
+    fn discovered(name: &str, discovered_at: f64) -> DiscoveredName {
+        DiscoveredName {
+            stage: "stage_0_1".to_string(),
+            name: name.to_string(),
+            ops: vec![],
+            discovered_at,
+        }
+    }
+
+    #[test]
+    fn onsen_status_merge_keeps_fewer_ops() {
+        let (ab, ba) = merged_both_ways(&status("stage_0_1", 5, "on"), &status("stage_0_1", 3, "not"));
+
+        assert_eq!(ab, ba);
+        assert_eq!(ab["ops_count"], 3);
+        assert_eq!(ab["onsen_name"]["roma"], "not");
+    }
+
+    #[test]
+    fn onsen_status_merge_keeps_stars_from_both() {
+        let mut a = status("stage_0_1", 5, "on");
+        a.is_clear = false;
+        a.is_using_onsen = true;
+        let mut b = status("stage_0_1", 5, "on");
+        b.use_hint = true;
+
+        let (ab, ba) = merged_both_ways(&a, &b);
+        assert_eq!(ab, ba);
+        assert_eq!(ab["is_clear"], true);
+        assert_eq!(ab["is_using_onsen"], true);
+        assert_eq!(ab["use_hint"], true);
+    }
+
+    #[test]
+    fn onsen_status_merge_breaks_ties_the_same_way() {
+        // ☆、操作回数、roma が同じで、珍しさだけが違う
+        let mut a = status("stage_0_1", 3, "on");
+        a.onsen_name.rarity = Some(10);
+        let mut b = status("stage_0_1", 3, "on");
+        b.onsen_name.rarity = Some(90);
+
+        let (ab, ba) = merged_both_ways(&a, &b);
+        assert_eq!(ab, ba);
+    }
+
+    #[test]
+    fn onsen_status_merge_prefers_no_hint_on_tie() {
+        let mut with_hint = status("stage_0_1", 3, "on");
+        with_hint.use_hint = true;
+        with_hint.onsen_name.rarity = Some(90);
+        let mut without_hint = status("stage_0_1", 3, "on");
+        without_hint.onsen_name.rarity = Some(10);
+
+        let (ab, ba) = merged_both_ways(&with_hint, &without_hint);
+        assert_eq!(ab, ba);
+        assert_eq!(ab["onsen_name"]["rarity"], 10);
+        // ヒントを使ったことは残す
+        assert_eq!(ab["use_hint"], true);
+    }
+
+    /// 二つのタブのセーブデータ
+    fn two_tabs() -> (UserStorage, UserStorage) {
+        let mut a = user_storage(&[status("stage_0_1", 5, "on"), OnsenStatus { key: "stage_0_2".to_string(), ..OnsenStatus::default() }]);
+        a.statistics("stage_0_1").record_attempt();
+        a.discovered_names.push(discovered("おん", 200.0));
+        a.complete_tutorial("top");
+
+        let mut b = user_storage(&[status("stage_0_1", 3, "not"), status("stage_0_2", 4, "or")]);
+        b.statistics("stage_0_1").record_attempt();
+        b.statistics("stage_0_1").record_attempt();
+        b.discovered_names.push(discovered("おん", 100.0));
+        b.discovered_names.push(discovered("のっと", 300.0));
+
+        (a, b)
+    }
+
+    #[test]
+    fn user_storage_merge_is_commutative() {
+        let (mut ab, b) = two_tabs();
+        ab.merge(&b);
+        let (a, mut ba) = two_tabs();
+        ba.merge(&a);
+
+        assert_eq!(serde_json::to_value(&ab).unwrap(), serde_json::to_value(&ba).unwrap());
+        assert_eq!(ab.onsen_status.onsen_status_list["stage_0_1"].ops_count, 3);
+        assert!(ab.onsen_status.onsen_status_list["stage_0_2"].is_cleared());
+        assert_eq!(ab.statistics["stage_0_1"].attempts, 2);
+        assert!(ab.is_tutorial_completed("top"));
+        // 同じ温泉名は先に見つけた方だけを残す
+        let discovered_at: Vec<f64> = ab.discovered_names.iter().map(|discovered| discovered.discovered_at).collect();
+        assert_eq!(discovered_at, vec![100.0, 300.0]);
+    }
+
+    #[test]
+    fn user_storage_merge_ignores_unknown_stages() {
+        let mut a = user_storage(&[status("stage_0_1", 5, "on")]);
+        let b = user_storage(&[status("stage_0_1", 5, "on"), status("stage_9_9", 1, "on")]);
+
+        a.merge(&b);
+        assert!(!a.onsen_status.onsen_status_list.contains_key("stage_9_9"));
+    }
+
+    #[test]
+    fn user_storage_merge_drops_progress_from_before_reset() {
+        let (mut old, mut reset) = two_tabs();
+        // 進行状況を消した後に stage_0_2 だけ遊んだ
+        reset.onsen_status.onsen_status_list.insert("stage_0_1".to_string(), OnsenStatus { key: "stage_0_1".to_string(), ..OnsenStatus::default() });
+        reset.statistics.clear();
+        reset.discovered_names.clear();
+        reset.reset_epoch = 1;
+
+        // 消したタブは古い進行状況を合わせない
+        let mut merged: UserStorage = serde_json::from_value(serde_json::to_value(&reset).unwrap()).unwrap();
+        merged.merge(&old);
+        assert_eq!(serde_json::to_value(&merged).unwrap(), serde_json::to_value(&reset).unwrap());
+
+        // 古いタブは消した後の進行状況に置き換える
+        old.merge(&reset);
+        assert_eq!(old.reset_epoch, 1);
+        assert!(!old.onsen_status.onsen_status_list["stage_0_1"].is_cleared());
+        assert!(old.onsen_status.onsen_status_list["stage_0_2"].is_cleared());
+        assert!(old.statistics.is_empty());
+        assert!(old.discovered_names.is_empty());
+        assert!(!old.is_tutorial_completed("top"));
+        assert_eq!(serde_json::to_value(&old).unwrap(), serde_json::to_value(&reset).unwrap());
+    }
+}
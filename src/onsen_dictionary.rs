@@ -0,0 +1,181 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+/// 辞書に載っている名のある温泉
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct FamousOnsen {
+    pub kanji: String,      // 表示名
+    pub reading: String,    // 読み
+    #[serde(default)]
+    pub flavour: String,    // 説明文
+    #[serde(default)]
+    pub english: String,    // 英語での呼び名 (空なら english_gloss で作る)
+}
+
+/// SenOp の並びのパターンと温泉の組
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DictionaryEntry {
+    /// SenOp の名前を空白区切りで並べたもの
+    /// "*" は 0 個以上の任意の SenOp, "?" は 1 個の任意の SenOp
+    pub pattern: String,
+    #[serde(flatten)]
+    pub onsen: FamousOnsen,
+}
+impl DictionaryEntry {
+    pub fn is_match(&self, ops: &[String]) -> bool {
+        let pattern: Vec<&str> = self.pattern.split_whitespace().collect();
+        Self::match_tokens(&pattern, ops)
+    }
+
+    fn match_tokens(pattern: &[&str], ops: &[String]) -> bool {
+        match pattern.split_first() {
+            None => ops.is_empty(),
+            Some((&"*", rest)) => (0..=ops.len()).any(|skip| Self::match_tokens(rest, &ops[skip..])),
+            Some((&token, rest)) => match ops.split_first() {
+                Some((op, ops)) => ("?" == token || token == op) && Self::match_tokens(rest, ops),
+                None => false,
+            },
+        }
+    }
+}
+
+/// 手書きの温泉名の辞書
+/// 先に書かれている項目ほど優先される
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct OnsenDictionary {
+    pub entries: Vec<DictionaryEntry>,
+}
+impl OnsenDictionary {
+    /// ops (SenOp の名前の並び) に一致する温泉を返す
+    pub fn lookup(&self, ops: &[String]) -> Option<&FamousOnsen> {
+        self.entries.iter()
+            .find(|entry| entry.is_match(ops))
+            .map(|entry| &entry.onsen)
+    }
+}
+
+/// SenOp の名前に対応する、英語での呼び名に使うおふざけの形容詞
+fn english_word(op: &str) -> &'static str {
+    match op {
+        "on" => "Warm",
+        "off" => "Sleepy",
+        "not" => "Contrary",
+        "or" => "Either-Way",
+        "and" => "Together",
+        "oro" => "Chosen",
+        "ando" => "Blended",
+        _ => "Mysterious",
+    }
+}
+
+/// ops (SenOp の名前の並び) から英語での呼び名を作る
+/// 同じ SenOp を何度も使うと "Extra-" を付けて、一度だけ並べる ("on on not" -> "The Extra-Warm & Contrary Hot Spring")
+pub fn english_gloss(ops: &[String]) -> String {
+    let mut words: Vec<(&str, usize)> = vec![];
+    for op in ops {
+        match words.iter_mut().find(|(word, _)| *word == english_word(op)) {
+            Some((_, count)) => *count += 1,
+            None => words.push((english_word(op), 1)),
+        }
+    }
+
+    let words: Vec<String> = words.iter()
+        .map(|(word, count)| format!("{}{}", if 1 < *count { "Extra-" } else { "" }, word))
+        .collect();
+    let adjectives = match words.split_last() {
+        None => return "The Hot Spring".to_string(),
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} & {}", rest.join(", "), last),
+    };
+
+    format!("The {} Hot Spring", adjectives)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ops(names: &str) -> Vec<String> {
+        names.split_whitespace().map(|name| name.to_string()).collect()
+    }
+
+    fn entry(pattern: &str) -> DictionaryEntry {
+        serde_json::from_value(serde_json::json!({
+            "pattern": pattern,
+            "kanji": "湯",
+            "reading": "ゆ",
+        })).unwrap()
+    }
+
+    #[test]
+    fn empty_ops() {
+        assert!(entry("").is_match(&ops("")));
+        assert!(entry("*").is_match(&ops("")));
+        assert!(entry("* *").is_match(&ops("")));
+        assert!(!entry("?").is_match(&ops("")));
+        assert!(!entry("on").is_match(&ops("")));
+    }
+
+    #[test]
+    fn trailing_star() {
+        let entry = entry("on not *");
+
+        assert!(entry.is_match(&ops("on not")));
+        assert!(entry.is_match(&ops("on not or and")));
+        assert!(!entry.is_match(&ops("on")));
+        assert!(!entry.is_match(&ops("not on not")));
+    }
+
+    #[test]
+    fn question_matches_exactly_one() {
+        let entry = entry("on ? not");
+
+        assert!(entry.is_match(&ops("on or not")));
+        assert!(!entry.is_match(&ops("on not")));
+        assert!(!entry.is_match(&ops("on or and not")));
+    }
+
+    #[test]
+    fn repeated_wildcards() {
+        assert!(entry("* * on").is_match(&ops("or and on")));
+        assert!(entry("? ? *").is_match(&ops("or and")));
+        assert!(!entry("? ? *").is_match(&ops("or")));
+        assert!(entry("* ? *").is_match(&ops("not")));
+        assert!(entry("* on * on *").is_match(&ops("or on and on")));
+        assert!(!entry("* on * on *").is_match(&ops("or on and")));
+    }
+
+    #[test]
+    fn lookup_prefers_earlier_entries() {
+        let dictionary = OnsenDictionary {
+            entries: vec![entry("on"), entry("*")],
+        };
+        let mut fallback = entry("*");
+        fallback.onsen.kanji = "泉".to_string();
+        let dictionary_with_fallback = OnsenDictionary {
+            entries: vec![entry("on"), fallback],
+        };
+
+        assert_eq!(dictionary.lookup(&ops("on")).unwrap().kanji, "湯");
+        assert_eq!(dictionary_with_fallback.lookup(&ops("not")).unwrap().kanji, "泉");
+        assert!(OnsenDictionary::default().lookup(&ops("on")).is_none());
+    }
+
+    #[test]
+    fn english_gloss_joins_words() {
+        assert_eq!(english_gloss(&ops("on")), "The Warm Hot Spring");
+        assert_eq!(english_gloss(&ops("on on not")), "The Extra-Warm & Contrary Hot Spring");
+        assert_eq!(english_gloss(&ops("on not or")), "The Warm, Contrary & Either-Way Hot Spring");
+    }
+
+    #[test]
+    fn english_gloss_fallbacks() {
+        // SenOp を使っていなければ形容詞を付けない
+        assert_eq!(english_gloss(&[]), "The Hot Spring");
+        // 知らない SenOp の名前
+        assert_eq!(english_gloss(&ops("xor")), "The Mysterious Hot Spring");
+        assert_eq!(english_gloss(&ops("xor nand")), "The Extra-Mysterious Hot Spring");
+    }
+}
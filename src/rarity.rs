@@ -0,0 +1,143 @@
+//! 温泉名の珍しさ
+//! クリア済みのステージでも、珍しい温泉名を探して遊べるようにする
+
+/// 温泉名の中に見つかると珍しさが上がる、実在する言葉 (SenOp の名前から作れるもの)
+const WORDS: &[(&str, &str)] = &[
+    ("あんど", "安堵"),
+    ("なんど", "何度"),
+    ("おんど", "温度"),
+    ("あんどん", "行灯"),
+    ("のど", "喉"),
+    ("どろ", "泥"),
+    ("とろ", "トロ"),
+    ("のろ", "鈍"),
+    ("おの", "斧"),
+    ("のと", "能登"),
+    ("おろおろ", "おろおろ"),
+];
+
+/// 珍しさの段階
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RarityTier {
+    Common,
+    Uncommon,
+    Rare,
+    Legendary,
+}
+impl RarityTier {
+    pub fn from_score(score: u32) -> Self {
+        match score {
+            0..=39 => Self::Common,
+            40..=59 => Self::Uncommon,
+            60..=79 => Self::Rare,
+            _ => Self::Legendary,
+        }
+    }
+
+    /// バッジに表示する文字
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Common => "並",
+            Self::Uncommon => "珍",
+            Self::Rare => "稀",
+            Self::Legendary => "幻",
+        }
+    }
+
+    /// バッジの css class
+    pub fn class_name(&self) -> &'static str {
+        match self {
+            Self::Common => "rarity_common",
+            Self::Uncommon => "rarity_uncommon",
+            Self::Rare => "rarity_rare",
+            Self::Legendary => "rarity_legendary",
+        }
+    }
+}
+
+/// name (ひらがな) に含まれる実在する言葉を返す
+pub fn find_words(name: &str) -> Vec<&'static str> {
+    WORDS.iter()
+        .filter(|(reading, _)| name.contains(reading))
+        .map(|(_, word)| *word)
+        .collect()
+}
+
+/// 珍しさを 0 ~ 100 で返す
+/// - 出やすさ (50点): そのステージをクリアできる並び total_count 通りのうち、この名前になるのが producer_count 通り
+///   探しきった上で出ないと分かった名前 (producer_count が 0) は一番珍しい (探しきれずに分からない時は呼ばない)
+/// - 長さ (25点): 長い名前ほど珍しい
+/// - 言葉 (25点): 名前そのものが言葉なら満点、言葉を含めば含む文字数に応じて
+pub fn score(name: &str, producer_count: usize, total_count: usize) -> u32 {
+    const MAX_LENGTH: usize = 12;
+
+    let frequency = if 0 == producer_count || 0 == total_count {
+        50.0
+    } else {
+        50.0 * (1.0 - producer_count as f64 / total_count as f64)
+    };
+
+    let length = name.chars().count();
+    let length_score = 25.0 * length.min(MAX_LENGTH) as f64 / MAX_LENGTH as f64;
+
+    let word_length = WORDS.iter()
+        .filter(|(reading, _)| name.contains(reading))
+        .map(|(reading, _)| reading.chars().count())
+        .max()
+        .unwrap_or(0);
+    let word_score = if 0 < length && word_length == length {
+        25.0
+    } else {
+        20.0 * word_length as f64 / length.max(1) as f64
+    };
+
+    (frequency + length_score + word_score).round().min(100.0) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tier_boundaries() {
+        assert_eq!(RarityTier::from_score(0), RarityTier::Common);
+        assert_eq!(RarityTier::from_score(39), RarityTier::Common);
+        assert_eq!(RarityTier::from_score(40), RarityTier::Uncommon);
+        assert_eq!(RarityTier::from_score(59), RarityTier::Uncommon);
+        assert_eq!(RarityTier::from_score(60), RarityTier::Rare);
+        assert_eq!(RarityTier::from_score(79), RarityTier::Rare);
+        assert_eq!(RarityTier::from_score(80), RarityTier::Legendary);
+        assert_eq!(RarityTier::from_score(100), RarityTier::Legendary);
+    }
+
+    #[test]
+    fn frequent_name_scores_lower() {
+        // "おん" は言葉を含まないので、出やすさと長さだけで決まる
+        assert_eq!(score("おん", 10, 10), 4);
+        assert_eq!(score("おん", 5, 10), 29);
+        assert_eq!(score("おん", 1, 10), 49);
+    }
+
+    #[test]
+    fn name_not_produced_gets_full_frequency() {
+        assert_eq!(score("おん", 0, 10), 54);
+        // クリアできる並びが無くても割らない
+        assert_eq!(score("おん", 0, 0), 54);
+    }
+
+    #[test]
+    fn words_raise_the_score() {
+        // 名前そのものが言葉なら満点、含むだけなら含む文字数に応じて
+        assert_eq!(score("おんど", 10, 10), 31);
+        assert_eq!(score("おんどおん", 10, 10), 22);
+        assert_eq!(find_words("おんどおん"), vec!["温度"]);
+    }
+
+    #[test]
+    fn rare_word_is_legendary() {
+        let score = score("おろおろ", 0, 10);
+
+        assert_eq!(score, 83);
+        assert_eq!(RarityTier::from_score(score), RarityTier::Legendary);
+    }
+}
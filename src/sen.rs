@@ -3,6 +3,8 @@ use serde::{
     Serialize,
 };
 
+use crate::settings::Palette;
+
 /// Sen の演算の種類
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum SenOp {
@@ -44,18 +46,19 @@ impl SenOp {
         }
     }
 
+    /// 画像のファイル名 (ディレクトリは配色の設定で決まる)
     pub fn to_file_name(&self) -> String {
         match self {
             Self::OrO(sen) | Self::AndO(sen) => {
                 let self_string = self.to_string();
                 let self_string = self_string.split_at(self_string.len()-1).0;
                 match sen.bit {
-                    0 => "/resource/image//mark_offsen.png".to_string(),
-                    2 => format!( "/resource/image//mark_{}sen.png", self_string ),
-                    _ => format!( "/resource/image//mark_{}sen_0b{:02b}.png", self_string, sen.bit ),
+                    0 => "mark_offsen.png".to_string(),
+                    2 => format!( "mark_{}sen.png", self_string ),
+                    _ => format!( "mark_{}sen_0b{:02b}.png", self_string, sen.bit ),
                 }
             }.to_lowercase(),
-            _ => format!( "/resource/image//mark_{}sen.png", self ).to_lowercase(),
+            _ => format!( "mark_{}sen.png", self ).to_lowercase(),
         }
     }
 
@@ -73,7 +76,7 @@ impl std::fmt::Display for SenOp {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct SenOpManager {
     sen_op_list: Vec<SenOp>,
     pub sen_index: usize,
@@ -108,6 +111,17 @@ impl SenOpManager {
         self.sen_index = (self.sen_index + 1) % self.sen_op_list.len()
     }
 
+    /// sen_op がリストにあれば、最初の位置を sen_index にする
+    pub fn select(&mut self, sen_op: SenOp) -> bool {
+        match self.sen_op_list.iter().position(|op| *op == sen_op) {
+            Some(index) => {
+                self.sen_index = index;
+                true
+            },
+            None => false,
+        }
+    }
+
     /// SenOp のリストに sen_index の位置から new_op_list を加える
     pub fn append_at_index(&mut self, new_op_list: &mut Vec<SenOp>) {
         let sen_index_next = self.sen_index + 1;
@@ -140,8 +154,14 @@ impl Sen {
         }
     }
 
+    /// 画像のファイル名 (ディレクトリは配色の設定で決まる)
     pub fn to_file_name(&self, index: usize) -> String {
-        format!("/resource/image/mark_{}sen_0b{:02b}.png", if index % 2 == 0 { "small_" } else { "" }, self.bit)
+        format!("mark_{}sen_0b{:02b}.png", if index % 2 == 0 { "small_" } else { "" }, self.bit)
+    }
+
+    /// 読み上げ用の色と値 ("赤 (0b10)" など、色は配色の設定で決まる)
+    pub fn describe(&self, palette: Palette) -> String {
+        format!("{} (0b{:02b})", palette.color_name(self.bit), self.bit)
     }
 }
 
@@ -160,6 +180,11 @@ impl SenManager {
         self.sen_list = self.sen_list.iter_mut().map(|sen| { (*sen).bit = 0; *sen } ).collect();
     }
 
+    /// sen の本数
+    pub fn len(&self) -> usize {
+        self.sen_list.len()
+    }
+
     /// 現在の sen_list を 2 進数へ変換して、つなげて i32 として返す
     pub fn get_number(&self) -> i32 {
         let binary_string = self.sen_list.as_slice().iter().map(|sen| format!("{:02b}", (*sen).bit) );
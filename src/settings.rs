@@ -0,0 +1,259 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::collections::BTreeMap;
+
+/// 温泉名をどの形で表示するか
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct NameForms {
+    pub romanized: bool,    // ヘボン式ローマ字
+    pub english: bool,      // 英語での呼び名
+}
+impl NameForms {
+    /// 設定されていない言語での表示 (日本語以外ならどちらも表示する)
+    fn default_for(locale: &str) -> Self {
+        let is_japanese = "ja" == locale;
+
+        Self {
+            romanized: !is_japanese,
+            english: !is_japanese,
+        }
+    }
+}
+
+/// 音量の設定 (音量は 0.0 ~ 1.0)
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AudioSettings {
+    pub master_volume: f64,
+    pub bgm_volume: f64,
+    pub se_volume: f64,
+    pub is_muted: bool,
+}
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.0,
+            bgm_volume: 1.0,
+            se_volume: 1.0,
+            is_muted: false,
+        }
+    }
+}
+
+/// 温泉マークの配色
+/// 線の状態 (0b00 ~ 0b11) を色だけで見分けられない人のために選べるようにする
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Palette {
+    Standard,       // 灰と赤
+    Universal,      // 赤の代わりに橙、反転を青系にした配色
+    Monochrome,     // 濃淡だけ
+}
+impl Palette {
+    pub const ALL: [Self; 3] = [Self::Standard, Self::Universal, Self::Monochrome];
+
+    /// 設定画面の select の value
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Standard => "standard",
+            Self::Universal => "universal",
+            Self::Monochrome => "monochrome",
+        }
+    }
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|palette| palette.id() == id)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Standard => "標準",
+            Self::Universal => "見分けやすい色",
+            Self::Monochrome => "白黒",
+        }
+    }
+
+    /// 読み上げ用の、線の状態 (0b00 ~ 0b11) の色の名前
+    pub fn color_name(&self, bit: u8) -> &'static str {
+        match (self, bit) {
+            (Self::Standard, 0b00) => "灰",
+            (Self::Standard, 0b10) => "赤",
+            (Self::Standard, 0b01) => "赤の反転",
+            (Self::Standard, _) => "灰の反転",
+            (Self::Universal, 0b00) => "灰",
+            (Self::Universal, 0b10) => "橙",
+            (Self::Universal, 0b01) => "青",
+            (Self::Universal, _) => "水色",
+            (Self::Monochrome, 0b00) => "白",
+            (Self::Monochrome, 0b10) => "黒",
+            (Self::Monochrome, 0b01) => "黒の反転",
+            (Self::Monochrome, _) => "白の反転",
+        }
+    }
+
+    /// 画像のディレクトリ (標準は今までの場所)
+    fn directory(&self) -> &'static str {
+        match self {
+            Self::Standard => "",
+            Self::Universal => "universal/",
+            Self::Monochrome => "monochrome/",
+        }
+    }
+}
+
+/// 見た目の設定
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ThemeSettings {
+    pub palette: Palette,
+    pub pattern_overlay: bool,  // 線の状態毎に模様を重ねた画像を使う
+}
+impl ThemeSettings {
+    const MARK_IMAGE_DIRECTORY: &'static str = "/resource/image/";
+
+    /// 温泉マークの画像 (Sen::to_file_name, SenOp::to_file_name) のパスを返す
+    /// 配色毎のディレクトリ (universal/, monochrome/) の下に、模様ありなら pattern/ を挟む
+    /// それらの画像を置いていない時は、読み込みに失敗した時に standard_mark_path の標準の画像へ差し替える
+    pub fn mark_path(&self, file_name: &str) -> String {
+        format!("{}{}{}{}",
+            Self::MARK_IMAGE_DIRECTORY,
+            self.palette.directory(),
+            if self.pattern_overlay { "pattern/" } else { "" },
+            file_name
+        )
+    }
+
+    /// mark_path で作った配色や模様の画像のパスを、標準の画像のパスにして返す
+    /// 温泉マークの画像でないか、既に標準の画像なら None
+    pub fn standard_mark_path(path: &str) -> Option<String> {
+        let file_name = path.strip_prefix(Self::MARK_IMAGE_DIRECTORY)?;
+        let file_name = Palette::ALL.iter()
+            .map(|palette| palette.directory())
+            .filter(|directory| !directory.is_empty())
+            .find_map(|directory| file_name.strip_prefix(directory))
+            .unwrap_or(file_name);
+        let file_name = file_name.strip_prefix("pattern/").unwrap_or(file_name);
+
+        let standard_path = format!("{}{}", Self::MARK_IMAGE_DIRECTORY, file_name);
+        if standard_path == path {
+            return None;
+        }
+
+        Some(standard_path)
+    }
+}
+impl Default for ThemeSettings {
+    fn default() -> Self {
+        Self {
+            palette: Palette::Standard,
+            pattern_overlay: false,
+        }
+    }
+}
+
+/// ユーザーの設定 (UserStorage に保存される)
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Settings {
+    #[serde(default = "Settings::detect_locale")]
+    pub locale: String,     // "ja", "en" など
+
+    // 言語毎の温泉名の表示
+    #[serde(default)]
+    pub name_forms: BTreeMap<String, NameForms>,
+
+    #[serde(default)]
+    pub audio: AudioSettings,
+
+    #[serde(default)]
+    pub theme: ThemeSettings,
+
+    // 扉やポップアップなどのアニメーションを減らす (None なら端末の prefers-reduced-motion に従う)
+    #[serde(default)]
+    pub reduced_motion: Option<bool>,
+}
+impl Settings {
+    /// 選べる言語 (locale と表示名)
+    pub const LOCALES: [(&'static str, &'static str); 2] = [("ja", "日本語"), ("en", "English")];
+
+    /// ブラウザの言語から最初の言語を決める
+    fn detect_locale() -> String {
+        let language = web_sys::window()
+            .and_then(|window| window.navigator().language())
+            .unwrap_or_default();
+
+        if language.starts_with("ja") { "ja" } else { "en" }.to_string()
+    }
+
+    /// 端末でアニメーションを減らす設定になっているか
+    fn prefers_reduced_motion() -> bool {
+        web_sys::window()
+            .and_then(|window| window.match_media("(prefers-reduced-motion: reduce)").ok().flatten())
+            .is_some_and(|media_query| media_query.matches())
+    }
+
+    /// アニメーションを減らすか
+    pub fn is_reduced_motion(&self) -> bool {
+        self.reduced_motion.unwrap_or_else(Self::prefers_reduced_motion)
+    }
+
+    /// 今の言語での温泉名の表示
+    pub fn name_forms(&self) -> NameForms {
+        self.name_forms.get(&self.locale).copied().unwrap_or_else(|| NameForms::default_for(&self.locale))
+    }
+
+    /// 今の言語での温泉名の表示を変える
+    pub fn set_name_forms(&mut self, name_forms: NameForms) {
+        self.name_forms.insert(self.locale.clone(), name_forms);
+    }
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            locale: Self::detect_locale(),
+            name_forms: BTreeMap::new(),
+            audio: AudioSettings::default(),
+            theme: ThemeSettings::default(),
+            reduced_motion: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mark_path_by_theme() {
+        let theme = ThemeSettings { palette: Palette::Universal, pattern_overlay: true };
+
+        assert_eq!(ThemeSettings::default().mark_path("mark_sen_0b10.png"), "/resource/image/mark_sen_0b10.png");
+        assert_eq!(theme.mark_path("mark_sen_0b10.png"), "/resource/image/universal/pattern/mark_sen_0b10.png");
+    }
+
+    #[test]
+    fn standard_mark_path_drops_palette_and_pattern() {
+        assert_eq!(ThemeSettings::standard_mark_path("/resource/image/universal/pattern/mark_sen_0b10.png").as_deref(), Some("/resource/image/mark_sen_0b10.png"));
+        assert_eq!(ThemeSettings::standard_mark_path("/resource/image/monochrome/mark_sen_0b10.png").as_deref(), Some("/resource/image/mark_sen_0b10.png"));
+        assert_eq!(ThemeSettings::standard_mark_path("/resource/image/pattern/mark_sen_0b10.png").as_deref(), Some("/resource/image/mark_sen_0b10.png"));
+    }
+
+    #[test]
+    fn standard_mark_path_ignores_standard_and_other_images() {
+        // 標準の画像も無ければ、差し替えを繰り返さない
+        assert_eq!(ThemeSettings::standard_mark_path("/resource/image/mark_sen_0b10.png"), None);
+        assert_eq!(ThemeSettings::standard_mark_path("/resource/image/wood_kanban.png"), None);
+        assert_eq!(ThemeSettings::standard_mark_path("/resource/bgm/select.mp3"), None);
+    }
+
+    #[test]
+    fn color_names_follow_palette() {
+        assert_eq!(Palette::Standard.color_name(0b10), "赤");
+        assert_eq!(Palette::Universal.color_name(0b10), "橙");
+        assert_eq!(Palette::Monochrome.color_name(0b10), "黒");
+        // どの配色でも、4 つの状態を違う名前で読み上げる
+        for palette in Palette::ALL.iter() {
+            let mut names: Vec<&str> = [0b00, 0b01, 0b10, 0b11].iter().map(|&bit| palette.color_name(bit)).collect();
+            names.sort_unstable();
+            names.dedup();
+            assert_eq!(names.len(), 4, "{:?}", palette);
+        }
+    }
+}
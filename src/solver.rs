@@ -0,0 +1,192 @@
+use crate::sen::{
+    SenManager,
+    SenOp,
+};
+use std::collections::HashSet;
+
+/// ステージをクリアできる SenOp の並びを探す
+/// 図鑑で「まだ見つけていない温泉名」を数えるために使う
+pub struct Solver {
+    target_temperature: i32,
+    target_bits: Vec<u8>,
+    max_depth: usize,
+    sequences: Vec<Vec<SenOp>>,
+    dead_states: HashSet<(String, usize)>,  // 残り手数ではクリアできないと分かった状態
+    visited_count: usize,
+}
+
+/// 探索結果
+pub struct Solution {
+    pub sequences: Vec<Vec<SenOp>>,
+    pub is_complete: bool,  // 探索しきれたか (false なら sequences は一部)
+}
+impl Solver {
+    // Or や And は線を変えずに SenOp を増やせるので、手数が多いと並びが爆発的に増える
+    // wasm で固まらないように、これ以上の状態は探索しない
+    const MAX_VISITED_COUNT: usize = 50_000;
+
+    /// sen から始めて max_depth 手以内で target_temperature になる SenOp の並びを全て返す
+    /// Off はやり直しなので使わない
+    /// 線を変えない操作も、QuastionPage::apply_sen_op と同じく一手として数えて温泉名に加える
+    pub fn clearing_sequences(sen: &SenManager, sen_op_list: &[SenOp], target_temperature: i32, max_depth: usize) -> Solution {
+        let target_bits = (0..sen.len()).rev()
+            .map(|shift| ((target_temperature >> (shift * 2)) & 0b11) as u8)
+            .collect();
+        let mut solver = Self {
+            target_temperature,
+            target_bits,
+            max_depth,
+            sequences: vec![],
+            dead_states: HashSet::new(),
+            visited_count: 0,
+        };
+        solver.search(sen, sen_op_list, &mut vec![]);
+
+        Solution {
+            is_complete: solver.visited_count < Self::MAX_VISITED_COUNT,
+            sequences: solver.sequences,
+        }
+    }
+
+    /// クリアまでに最低限必要な手数
+    /// On 以外は一手で一本の線しか変えられず、On を使うと全ての線が ON になるので、そこから違う線は一手ずつかかる
+    fn lower_bound(&self, sen: &SenManager) -> usize {
+        let mut differ = 0;
+        let mut differ_from_on = 0;
+        sen.for_each(|(index, sen)| {
+            differ += (sen.bit != self.target_bits[index]) as usize;
+            differ_from_on += (SenOp::ON != self.target_bits[index]) as usize;
+        });
+
+        differ.min(1 + differ_from_on)
+    }
+
+    /// 見つかった並びがあれば true を返す
+    fn search(&mut self, sen: &SenManager, sen_op_list: &[SenOp], history: &mut Vec<SenOp>) -> bool {
+        let remaining = self.max_depth - history.len();
+        if remaining < self.lower_bound(sen) || Self::MAX_VISITED_COUNT <= self.visited_count {
+            return false;
+        }
+        self.visited_count += 1;
+
+        // SenOp の並び順は結果に関係ないので、並べ替えたものを状態とする
+        let mut op_names: Vec<String> = sen_op_list.iter().map(|sen_op| format!("{:?}", sen_op)).collect();
+        op_names.sort();
+        let state = (format!("{:?}{:?}", sen, op_names), remaining);
+        if self.dead_states.contains(&state) {
+            return false;
+        }
+
+        let mut found = false;
+        for (op_index, sen_op) in sen_op_list.iter().enumerate() {
+            // 同じ SenOp が複数あっても結果は同じなので最初の一つだけ使う
+            if sen_op_list[..op_index].contains(sen_op) || SenOp::Off == *sen_op {
+                continue;
+            }
+
+            // On は全ての線に、それ以外は一本ずつ適用する
+            let targets: Vec<Option<usize>> = match sen_op {
+                SenOp::On => vec![None],
+                _ => (0..sen.len()).map(Some).collect(),
+            };
+            for target in targets {
+                let mut next_sen = sen.clone();
+                let new_op = next_sen.operation(sen_op, &mut |(index, _sen)| target.is_none_or(|target| target == index));
+
+                // QuastionPage::moved_onsen_mark と同じく、変化があった O は消費して、出力は追加する
+                let mut next_op_list = sen_op_list.to_vec();
+                if sen_op.is_o() && next_sen != *sen {
+                    next_op_list.remove(op_index);
+                }
+                next_op_list.extend(new_op);
+
+                history.push(*sen_op);
+                if next_sen.get_number() == self.target_temperature {
+                    self.sequences.push(history.clone());
+                    self.visited_count += 1;
+                    found = true;
+                } else {
+                    found |= self.search(&next_sen, &next_op_list, history);
+                }
+                history.pop();
+            }
+        }
+
+        if !found && self.visited_count < Self::MAX_VISITED_COUNT {
+            self.dead_states.insert(state);
+        }
+
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEN_OP_LIST: [SenOp; 5] = [SenOp::Off, SenOp::On, SenOp::Or, SenOp::And, SenOp::Not];
+
+    /// 線の値から SenManager を作る
+    fn sen_manager(bits: &[u8]) -> SenManager {
+        let sen_list: Vec<String> = bits.iter().map(|bit| format!(r#"{{"bit":{}}}"#, bit)).collect();
+        serde_json::from_str(&format!(r#"{{"sen_list":[{}]}}"#, sen_list.join(","))).unwrap()
+    }
+
+    /// 並び順に依らずに比べられるように、並びを文字列にして並べ替える
+    fn sorted(sequences: &[Vec<SenOp>]) -> Vec<String> {
+        let mut sequences: Vec<String> = sequences.iter().map(|sequence| format!("{:?}", sequence)).collect();
+        sequences.sort();
+        sequences
+    }
+
+    #[test]
+    fn one_move_stage() {
+        let solution = Solver::clearing_sequences(&sen_manager(&[0b00]), &SEN_OP_LIST, 0b10, 1);
+
+        assert!(solution.is_complete);
+        assert_eq!(sorted(&solution.sequences), vec!["[On]"]);
+    }
+
+    #[test]
+    fn two_move_stage_uses_outputs() {
+        let solution = Solver::clearing_sequences(&sen_manager(&[0b00]), &SEN_OP_LIST, 0b10, 2);
+
+        assert!(solution.is_complete);
+        assert_eq!(sorted(&solution.sequences), vec![
+            "[And, On]",
+            "[Not, On]",
+            "[On]",
+            "[Or, On]",
+            "[Or, OrO(Sen { bit: 2 })]",
+        ]);
+    }
+
+    #[test]
+    fn too_few_moves_finds_nothing() {
+        // 0b01 は On してから Not するしかない
+        let sen = sen_manager(&[0b00]);
+
+        let solution = Solver::clearing_sequences(&sen, &SEN_OP_LIST, 0b01, 1);
+        assert!(solution.is_complete);
+        assert!(solution.sequences.is_empty());
+
+        let solution = Solver::clearing_sequences(&sen, &SEN_OP_LIST, 0b01, 2);
+        assert_eq!(sorted(&solution.sequences), vec!["[On, Not]"]);
+    }
+
+    #[test]
+    fn each_sen_is_changed_separately() {
+        // 2 本の線の片方だけを Not する
+        let solution = Solver::clearing_sequences(&sen_manager(&[0b00, 0b00]), &SEN_OP_LIST, 0b0011, 1);
+
+        assert_eq!(sorted(&solution.sequences), vec!["[Not]"]);
+    }
+
+    #[test]
+    fn moves_without_change_are_counted() {
+        // 既に ON の線に On しても変わらないが、ゲームでは一手として温泉名にも入る
+        let solution = Solver::clearing_sequences(&sen_manager(&[0b10]), &SEN_OP_LIST, 0b01, 2);
+
+        assert!(sorted(&solution.sequences).contains(&"[On, Not]".to_string()));
+    }
+}
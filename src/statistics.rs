@@ -0,0 +1,203 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::collections::BTreeMap;
+
+/// ステージ毎のプレイ統計
+/// 難しすぎる/簡単すぎるステージを探すために使う
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct StageStatistics {
+    pub attempts: u32,                      // 挑戦回数
+    pub clears: u32,                        // クリア回数
+    pub resets: u32,                        // Off でのリセット回数
+    pub hints_used: u32,                    // ヒントを使った回数
+    pub best_ops_count: Option<i32>,        // 最少操作回数
+    pub first_clear_time: Option<f64>,      // 初クリアの日時 (js::time::now の ms)
+    pub best_clear_time: Option<f64>,       // 入ってからクリアまでの最短時間 (ms)
+    pub ops_histogram: BTreeMap<String, u32>, // SenOp 毎の使用回数
+}
+impl StageStatistics {
+    /// ステージに入った
+    pub fn record_attempt(&mut self) {
+        self.attempts += 1;
+    }
+
+    /// SenOp を使った
+    pub fn record_op(&mut self, op_name: &str) {
+        *self.ops_histogram.entry(op_name.to_string()).or_insert(0) += 1;
+    }
+
+    /// Off でリセットした
+    pub fn record_reset(&mut self) {
+        self.resets += 1;
+    }
+
+    /// ヒントを使った
+    pub fn record_hint(&mut self) {
+        self.hints_used += 1;
+    }
+
+    /// クリアした, started_at は入った(リセットした)時刻, now はクリアした時刻
+    pub fn record_clear(&mut self, ops_count: i32, started_at: f64, now: f64) {
+        self.clears += 1;
+        self.best_ops_count = Some(self.best_ops_count.map_or(ops_count, |best| best.min(ops_count)));
+        self.first_clear_time.get_or_insert(now);
+
+        let clear_time = (now - started_at).max(0.0);
+        self.best_clear_time = Some(self.best_clear_time.map_or(clear_time, |best| best.min(clear_time)));
+    }
+
+    /// 別タブや同期先で記録された統計と合わせる
+    /// 回数は大きい方、記録は良い方をとる (どちらから合わせても同じ結果になり、何度合わせても増えない)
+    /// 別タブは保存する度に合わせるので、同じ保存データから数えた回数の大きい方が正しい
+    /// 同期では前に合わせた後に両方の端末で数えた分が足されないので、回数は実際より少ないことがある (少なくともこれだけ、という値)
+    /// 端末毎に数えれば足し合わせられるが、難しさの目安には十分なのでそうしていない
+    pub fn merge(&mut self, other: &Self) {
+        fn min_option<T: PartialOrd + Copy>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(if b < a { b } else { a }),
+                (a, b) => a.or(b),
+            }
+        }
+
+        self.attempts = self.attempts.max(other.attempts);
+        self.clears = self.clears.max(other.clears);
+        self.resets = self.resets.max(other.resets);
+        self.hints_used = self.hints_used.max(other.hints_used);
+        self.best_ops_count = min_option(self.best_ops_count, other.best_ops_count);
+        self.first_clear_time = min_option(self.first_clear_time, other.first_clear_time);
+        self.best_clear_time = min_option(self.best_clear_time, other.best_clear_time);
+        for (op_name, &count) in &other.ops_histogram {
+            let own_count = self.ops_histogram.entry(op_name.clone()).or_insert(0);
+            *own_count = (*own_count).max(count);
+        }
+    }
+
+    /// クリア率 (挑戦していなければ None)
+    pub fn clear_rate(&self) -> Option<f64> {
+        if 0 == self.attempts {
+            return None;
+        }
+
+        Some(self.clears as f64 / self.attempts as f64)
+    }
+
+    /// 一番使われた SenOp
+    pub fn favorite_op(&self) -> Option<&str> {
+        self.ops_histogram.iter()
+            .max_by_key(|(_, count)| **count)
+            .map(|(op_name, _)| op_name.as_str())
+    }
+
+    /// ms を "分:秒" の文字列にする
+    pub fn format_duration(ms: f64) -> String {
+        let seconds = (ms / 1000.0).floor() as u64;
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_counts() {
+        let mut statistics = StageStatistics::default();
+        statistics.record_attempt();
+        statistics.record_attempt();
+        statistics.record_op("on");
+        statistics.record_op("not");
+        statistics.record_op("on");
+        statistics.record_reset();
+        statistics.record_hint();
+
+        assert_eq!(statistics.attempts, 2);
+        assert_eq!(statistics.resets, 1);
+        assert_eq!(statistics.hints_used, 1);
+        assert_eq!(statistics.ops_histogram["on"], 2);
+        assert_eq!(statistics.favorite_op(), Some("on"));
+        assert_eq!(statistics.clear_rate(), Some(0.0));
+    }
+
+    #[test]
+    fn record_clear_keeps_best() {
+        let mut statistics = StageStatistics::default();
+        statistics.record_clear(5, 1000.0, 61_000.0);
+        statistics.record_clear(3, 100_000.0, 190_000.0);
+        statistics.record_clear(4, 200_000.0, 230_000.0);
+
+        assert_eq!(statistics.clears, 3);
+        assert_eq!(statistics.best_ops_count, Some(3));
+        assert_eq!(statistics.first_clear_time, Some(61_000.0));
+        assert_eq!(statistics.best_clear_time, Some(30_000.0));
+        assert_eq!(StageStatistics::format_duration(statistics.best_clear_time.unwrap()), "0:30");
+        assert_eq!(StageStatistics::format_duration(61_000.0), "1:01");
+    }
+
+    #[test]
+    fn clear_rate_without_attempts() {
+        assert_eq!(StageStatistics::default().clear_rate(), None);
+    }
+
+    fn recorded(attempts: u32, ops: &[&str], clear: Option<(i32, f64, f64)>) -> StageStatistics {
+        let mut statistics = StageStatistics::default();
+        for _ in 0..attempts {
+            statistics.record_attempt();
+        }
+        for op in ops {
+            statistics.record_op(op);
+        }
+        if let Some((ops_count, started_at, now)) = clear {
+            statistics.record_clear(ops_count, started_at, now);
+        }
+
+        statistics
+    }
+
+    #[test]
+    fn merge_takes_larger_counts_and_better_records() {
+        let a = recorded(3, &["on", "on"], Some((5, 0.0, 50_000.0)));
+        let b = recorded(2, &["on", "not", "not", "not"], Some((4, 10_000.0, 90_000.0)));
+
+        let mut ab = a.clone();
+        ab.merge(&b);
+        let mut ba = b.clone();
+        ba.merge(&a);
+
+        assert_eq!(ab, ba);
+        assert_eq!(ab.attempts, 3);
+        assert_eq!(ab.clears, 1);
+        assert_eq!(ab.ops_histogram["on"], 2);
+        assert_eq!(ab.ops_histogram["not"], 3);
+        assert_eq!(ab.best_ops_count, Some(4));
+        assert_eq!(ab.first_clear_time, Some(50_000.0));
+        assert_eq!(ab.best_clear_time, Some(50_000.0));
+    }
+
+    #[test]
+    fn merge_is_idempotent() {
+        let a = recorded(3, &["on"], Some((5, 0.0, 50_000.0)));
+        let mut merged = a.clone();
+        merged.merge(&a);
+        merged.merge(&a);
+
+        assert_eq!(merged, a);
+    }
+
+    #[test]
+    fn merge_after_sync_is_a_lower_bound() {
+        // 同期した後に二つの端末でそれぞれ挑戦すると、合わせた回数は大きい方だけになる
+        let base = recorded(2, &[], None);
+        let mut laptop = base.clone();
+        laptop.record_attempt();
+        let mut phone = base.clone();
+        phone.record_attempt();
+        phone.record_attempt();
+
+        laptop.merge(&phone);
+        // 実際は 2 + 1 + 2 = 5 回
+        assert_eq!(laptop.attempts, 4);
+    }
+}
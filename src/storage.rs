@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+
+/// セーブデータの保存先を抽象化したもの
+/// name をファイル名の代わりにして文字列をやり取りする
+pub trait Storage {
+    /// name のデータを返す、無ければ None
+    fn read(&self, name: &str) -> Option<String>;
+
+    /// name へ data を書き込む、書き込めたかどうかを返す
+    fn write(&mut self, name: &str, data: &str) -> bool;
+}
+
+/// メモリ上だけで保持する Storage
+/// ネイティブでのテストや、プライベートブラウズで localStorage が使えない時の代替
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    data: HashMap<String, String>,
+}
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+impl Storage for MemoryStorage {
+    fn read(&self, name: &str) -> Option<String> {
+        self.data.get(name).cloned()
+    }
+
+    fn write(&mut self, name: &str, data: &str) -> bool {
+        self.data.insert(name.to_string(), data.to_string());
+        true
+    }
+}
+
+/// window.localStorage を使う Storage
+#[cfg(target_arch = "wasm32")]
+pub struct LocalStorage {
+    storage: web_sys::Storage,
+}
+#[cfg(target_arch = "wasm32")]
+impl LocalStorage {
+    /// localStorage が使えない (存在しない、アクセスが拒否される) 場合は None
+    pub fn new() -> Option<Self> {
+        let window = web_sys::window()?;
+        let storage = window.local_storage().ok()??;
+
+        Some(Self {
+            storage,
+        })
+    }
+
+    /// 保存されている全ての (name, data)
+    fn entries(&self) -> Vec<(String, String)> {
+        let length = self.storage.length().unwrap_or(0);
+        (0..length)
+            .filter_map(|index| self.storage.key(index).ok()?)
+            .filter_map(|name| Some((name.clone(), self.read(&name)?)))
+            .collect()
+    }
+}
+#[cfg(target_arch = "wasm32")]
+impl Storage for LocalStorage {
+    fn read(&self, name: &str) -> Option<String> {
+        self.storage.get_item(name).ok()?
+    }
+
+    fn write(&mut self, name: &str, data: &str) -> bool {
+        // 容量超過やプライベートブラウズだと例外が投げられる
+        self.storage.set_item(name, data).is_ok()
+    }
+}
+
+/// IndexedDB を使う Storage (リプレイや統計などの大きいデータ用)
+/// IndexedDB は非同期なので、open 時に全件をメモリへ読み込んでおき
+/// read はメモリから返し、write はメモリへ反映してから IndexedDB へ非同期で書き込む
+#[cfg(target_arch = "wasm32")]
+pub struct IndexedDbStorage {
+    db_name: String,
+    cache: MemoryStorage,
+}
+#[cfg(target_arch = "wasm32")]
+impl IndexedDbStorage {
+    /// db_name の IndexedDB を開いて中身を読み込む
+    pub async fn open(db_name: &str) -> Result<Self, wasm_bindgen::JsValue> {
+        let entries = wasm_bindgen_futures::JsFuture::from( indexed_db::get_all(db_name, indexed_db::STORE_NAME) ).await?;
+
+        let mut cache = MemoryStorage::new();
+        for key in js_sys::Object::keys(&js_sys::Object::from(entries.clone())).iter() {
+            if let (Some(name), Some(data)) = (key.as_string(), js_sys::Reflect::get(&entries, &key)?.as_string()) {
+                cache.write(&name, &data);
+            }
+        }
+
+        Ok(Self {
+            db_name: db_name.to_string(),
+            cache,
+        })
+    }
+
+    /// 書き込み用の Promise を投げっぱなしにして、失敗したら console に出す
+    fn spawn_request(promise: js_sys::Promise, name: &str) {
+        let name = name.to_string();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(e) = wasm_bindgen_futures::JsFuture::from(promise).await {
+                crate::js::console_log!("failed IndexedDB request [{}] E:{:?}", name, e);
+            }
+        });
+    }
+}
+#[cfg(target_arch = "wasm32")]
+impl Storage for IndexedDbStorage {
+    fn read(&self, name: &str) -> Option<String> {
+        self.cache.read(name)
+    }
+
+    fn write(&mut self, name: &str, data: &str) -> bool {
+        self.cache.write(name, data);
+        Self::spawn_request( indexed_db::put(&self.db_name, indexed_db::STORE_NAME, name, data), name );
+        true
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod indexed_db {
+    use wasm_bindgen::prelude::*;
+
+    pub const DB_NAME: &str = "onsen";
+    pub const STORE_NAME: &str = "storage";
+
+    // 書き込んだことを他のタブへ知らせる (IndexedDB には storage イベントが無いので)
+    #[wasm_bindgen(inline_js = "
+        const channel = ('BroadcastChannel' in self) ? new BroadcastChannel('onsen_storage') : null;
+        function open_db(db_name, store) {
+            return new Promise(function(resolve, reject) {
+                if (!('indexedDB' in self)) { reject(new Error('indexedDB is not available')); return; }
+                let request = indexedDB.open(db_name, 1);
+                request.onupgradeneeded = function() { request.result.createObjectStore(store); };
+                request.onsuccess = function() { resolve(request.result); };
+                request.onerror = function() { reject(request.error); };
+            });
+        }
+        function transaction(db_name, store, mode, f) {
+            return open_db(db_name, store).then(function(db) {
+                return new Promise(function(resolve, reject) {
+                    let tx = db.transaction(store, mode);
+                    let result = f(tx.objectStore(store));
+                    tx.oncomplete = function() { db.close(); resolve(result); };
+                    tx.onerror = function() { db.close(); reject(tx.error); };
+                });
+            });
+        }
+        export function get_all(db_name, store) {
+            return transaction(db_name, store, 'readonly', function(object_store) {
+                let result = {};
+                object_store.openCursor().onsuccess = function(event) {
+                    let cursor = event.target.result;
+                    if (cursor) { result[cursor.key] = cursor.value; cursor.continue(); }
+                };
+                return result;
+            });
+        }
+        export function put(db_name, store, key, value) {
+            return transaction(db_name, store, 'readwrite', function(object_store) { object_store.put(value, key); })
+                .then(function() { if (channel) { channel.postMessage([key, value]); } });
+        }
+        export function add_change_listener(f) {
+            if (channel) { channel.onmessage = function(event) { f(event.data[0], event.data[1]); }; }
+        }
+    ")]
+    extern "C" {
+        /// store の全件を { key: value } で返す
+        pub fn get_all(db_name: &str, store: &str) -> js_sys::Promise;
+
+        /// store の key へ value を書き込む
+        pub fn put(db_name: &str, store: &str, key: &str, value: &str) -> js_sys::Promise;
+
+        /// 別タブで書き込まれた時に f(key, value) を呼ぶ
+        pub fn add_change_listener(f: &Closure<dyn FnMut(String, Option<String>)>);
+    }
+}
+
+/// 別タブで保存データが書き換えられた時に f(key, new_value) を呼ぶ
+/// localStorage は storage イベント、IndexedDB は BroadcastChannel で受け取る (書き換えたタブ自身には通知されない)
+pub fn add_storage_listener<F>(f: F)
+where
+    F: FnMut(String, Option<String>) + 'static,
+{
+    use wasm_bindgen::JsCast;
+
+    let f = std::rc::Rc::new(std::cell::RefCell::new(f));
+
+    let window = web_sys::window().expect("no global `window` exist");
+    let storage_f = f.clone();
+    let closure = wasm_bindgen::closure::Closure::wrap(Box::new(move |event: web_sys::StorageEvent| {
+        // key が None なのは localStorage.clear() された時
+        if let Some(key) = event.key() {
+            (storage_f.borrow_mut())(key, event.new_value());
+        }
+    }) as Box<dyn FnMut(web_sys::StorageEvent)>);
+    window.add_event_listener_with_callback("storage", closure.as_ref().unchecked_ref()).unwrap();
+    // ページが開いている間はずっと監視するので開放しない
+    closure.forget();
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let closure = wasm_bindgen::closure::Closure::wrap(Box::new(move |key: String, new_value: Option<String>| {
+            (f.borrow_mut())(key, new_value);
+        }) as Box<dyn FnMut(String, Option<String>)>);
+        indexed_db::add_change_listener(&closure);
+        closure.forget();
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+thread_local! {
+    // open_default_storage で開いた Storage (default_storage で一度だけ取り出す)
+    static OPENED_STORAGE: std::cell::RefCell<Option<Box<dyn Storage>>> = std::cell::RefCell::new(None);
+}
+
+/// セーブデータ用の Storage を開いておく (IndexedDB は非同期でしか開けないので、表示する前に待つ)
+/// IndexedDB が使えなければ localStorage、それも使えなければメモリ上で保持する (リロードで消える)
+#[cfg(target_arch = "wasm32")]
+pub async fn open_default_storage() {
+    let storage: Box<dyn Storage> = match IndexedDbStorage::open(indexed_db::DB_NAME).await {
+        Ok(mut storage) => {
+            // IndexedDB を使う前に localStorage へ保存していたデータを移す
+            if let Some(local_storage) = LocalStorage::new() {
+                for (name, data) in local_storage.entries() {
+                    if storage.read(&name).is_none() {
+                        crate::js::console_log!("migrate {} from localStorage to IndexedDB", name);
+                        storage.write(&name, &data);
+                    }
+                }
+            }
+            Box::new(storage)
+        },
+        Err(e) => {
+            crate::js::console_log!("IndexedDB is not available. use localStorage. E:{:?}", e);
+            default_storage()
+        },
+    };
+
+    OPENED_STORAGE.with(|opened| *opened.borrow_mut() = Some(storage));
+}
+
+/// 環境に合わせてセーブデータ用の Storage を返す
+/// open_default_storage で開いていればそれを、無ければ localStorage、それも使えなければメモリ上で保持する
+pub fn default_storage() -> Box<dyn Storage> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        if let Some(storage) = OPENED_STORAGE.with(|opened| opened.borrow_mut().take()) {
+            return storage;
+        }
+        if let Some(storage) = LocalStorage::new() {
+            return Box::new(storage);
+        }
+        crate::js::console_log!("localStorage is not available. use memory storage.");
+    }
+
+    Box::new(MemoryStorage::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_storage_reads_what_was_written() {
+        let mut storage = MemoryStorage::new();
+
+        assert_eq!(storage.read("save_data.json"), None);
+        assert!(storage.write("save_data.json", "{}"));
+        assert_eq!(storage.read("save_data.json"), Some("{}".to_string()));
+        assert_eq!(storage.read("other.json"), None);
+    }
+
+    #[test]
+    fn memory_storage_overwrites() {
+        let mut storage = MemoryStorage::new();
+
+        storage.write("save_data.json", "old");
+        storage.write("save_data.json", "new");
+        assert_eq!(storage.read("save_data.json"), Some("new".to_string()));
+    }
+
+    #[test]
+    fn default_storage_is_memory_on_native() {
+        let mut storage = default_storage();
+
+        assert!(storage.write("save_data.json", "{}"));
+        assert_eq!(storage.read("save_data.json"), Some("{}".to_string()));
+    }
+}
@@ -0,0 +1,366 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+/// 同期先に保存されている進行状況
+/// revision は保存される度に増えて、押し戻す時に元にした revision を送ることで衝突を検出する
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Snapshot {
+    pub revision: u64,
+    pub payload: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SyncError {
+    Conflict,           // 別の端末が先に push した
+    Http(u16),          // 想定外のステータスコード
+    Network(String),    // 接続できない、レスポンスが読めない
+}
+
+/// 進行状況を端末間でやり取りする同期先
+/// wasm の単一スレッドでしか待たないので、返す Future に Send は求めない
+#[allow(async_fn_in_trait)]
+pub trait SyncBackend {
+    /// 同期先の進行状況を返す, まだ一度も push されていなければ None
+    async fn pull(&mut self) -> Result<Option<Snapshot>, SyncError>;
+
+    /// snapshot.revision を元にした payload を保存して、新しい revision を返す
+    /// 同期先の revision が snapshot.revision と違えば Err(SyncError::Conflict)
+    async fn push(&mut self, snapshot: &Snapshot) -> Result<u64, SyncError>;
+}
+
+/// 衝突し続けた時に諦めるまでの回数
+const MAX_RETRY: usize = 4;
+
+/// pull して merge したものを push する, 衝突したら pull からやり直す
+/// merge には同期先の payload (無ければ None) が渡されるので、手元と合わせた payload を返す
+/// 最後に push した payload を返す
+pub async fn synchronize<B, F>(backend: &mut B, mut merge: F) -> Result<String, SyncError>
+where
+    B: SyncBackend + ?Sized,
+    F: FnMut(Option<&str>) -> String,
+{
+    for _ in 0..MAX_RETRY {
+        let remote = backend.pull().await?;
+        let snapshot = Snapshot {
+            revision: remote.as_ref().map_or(0, |remote| remote.revision),
+            payload: merge(remote.as_ref().map(|remote| remote.payload.as_str())),
+        };
+
+        match backend.push(&snapshot).await {
+            Ok(_) => return Ok(snapshot.payload),
+            Err(SyncError::Conflict) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(SyncError::Conflict)
+}
+
+/// HTTP の同期先
+/// GET  {endpoint}/progress/{user_id} -> 200 Snapshot | 404
+/// PUT  {endpoint}/progress/{user_id} <- Snapshot -> 200 Snapshot(revision のみ) | 409
+pub struct HttpSyncBackend {
+    endpoint: String,
+    user_id: String,
+}
+impl HttpSyncBackend {
+    pub fn new(endpoint: &str, user_id: &str) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            user_id: user_id.to_string(),
+        }
+    }
+
+    fn url(&self) -> String {
+        format!("{}/progress/{}", self.endpoint, self.user_id)
+    }
+}
+impl SyncBackend for HttpSyncBackend {
+    async fn pull(&mut self) -> Result<Option<Snapshot>, SyncError> {
+        match http::request("GET", &self.url(), "").await? {
+            (200, body) => serde_json::from_str(&body)
+                .map(Some)
+                .map_err(|e| SyncError::Network(e.to_string())),
+            (404, _) => Ok(None),
+            (status, _) => Err(SyncError::Http(status)),
+        }
+    }
+
+    async fn push(&mut self, snapshot: &Snapshot) -> Result<u64, SyncError> {
+        match http::request("PUT", &self.url(), &serde_json::to_string(snapshot).unwrap()).await? {
+            (200, body) => serde_json::from_str::<Snapshot>(&body)
+                .map(|saved| saved.revision)
+                .map_err(|e| SyncError::Network(e.to_string())),
+            (409, _) => Err(SyncError::Conflict),
+            (status, _) => Err(SyncError::Http(status)),
+        }
+    }
+}
+
+/// wasm では fetch で送り、応答を待つ間も画面を止めない
+#[cfg(target_arch = "wasm32")]
+mod http {
+    use super::SyncError;
+    use wasm_bindgen::prelude::*;
+    use wasm_bindgen_futures::JsFuture;
+
+    #[wasm_bindgen(inline_js = "export function send_request(method, url, body) { return fetch(url, { method: method, headers: { 'Content-Type': 'application/json' }, body: body || null }).then(response => response.text().then(text => [response.status, text])).catch(e => [0, String(e)]); }")]
+    extern "C" {
+        /// [status, responseText] で解決する Promise を返す, 接続できなければ status は 0
+        fn send_request(method: &str, url: &str, body: &str) -> js_sys::Promise;
+    }
+
+    pub async fn request(method: &str, url: &str, body: &str) -> Result<(u16, String), SyncError> {
+        let response = JsFuture::from(send_request(method, url, body)).await
+            .map_err(|e| SyncError::Network(format!("{:?}", e)))?
+            .unchecked_into::<js_sys::Array>();
+        let status = response.get(0).as_f64().unwrap_or(0.0) as u16;
+        let text = response.get(1).as_string().unwrap_or_default();
+
+        match status {
+            0 => Err(SyncError::Network(text)),
+            _ => Ok((status, text)),
+        }
+    }
+}
+
+/// ネイティブ (テストやモックサーバーの確認用) では TcpStream で最低限の HTTP/1.1 を話す
+#[cfg(not(target_arch = "wasm32"))]
+mod http {
+    use super::SyncError;
+    use std::io::{
+        Read,
+        Write,
+    };
+
+    /// テストでしか使わないので、待たずにそのまま送受信する
+    pub async fn request(method: &str, url: &str, body: &str) -> Result<(u16, String), SyncError> {
+        let network_error = |e: std::io::Error| SyncError::Network(e.to_string());
+
+        let rest = url.strip_prefix("http://")
+            .ok_or_else(|| SyncError::Network(format!("unsupported url {}", url)))?;
+        let (host, path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, "/"),
+        };
+
+        let mut stream = std::net::TcpStream::connect(host).map_err(network_error)?;
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method, path, host, body.len(), body,
+        ).map_err(network_error)?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).map_err(network_error)?;
+
+        let status = response.split(' ').nth(1)
+            .and_then(|status| status.parse::<u16>().ok())
+            .ok_or_else(|| SyncError::Network(format!("invalid response {:?}", response)))?;
+        let body = response.split_once("\r\n\r\n").map_or("", |(_, body)| body);
+
+        Ok((status, body.to_string()))
+    }
+}
+
+/// 開発用のモックサーバー (src/bin/mock_sync_server.rs とテストで使う)
+/// 進行状況はメモリ上にだけ保持する
+#[cfg(not(target_arch = "wasm32"))]
+pub mod mock_server {
+    use super::Snapshot;
+    use std::collections::HashMap;
+    use std::io::{
+        BufRead,
+        BufReader,
+        Read,
+        Write,
+    };
+    use std::net::{
+        TcpListener,
+        TcpStream,
+    };
+    use std::sync::{
+        Arc,
+        Mutex,
+    };
+
+    type Progress = Arc<Mutex<HashMap<String, Snapshot>>>;
+
+    /// listener に来たリクエストを捌き続ける
+    pub fn serve(listener: TcpListener) {
+        let progress = Progress::default();
+
+        for stream in listener.incoming().flatten() {
+            let progress = progress.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = handle(stream, &progress) {
+                    eprintln!("failed request E:{:?}", e);
+                }
+            });
+        }
+    }
+
+    fn handle(stream: TcpStream, progress: &Progress) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut request_line = request_line.split_whitespace();
+        let method = request_line.next().unwrap_or("").to_string();
+        let path = request_line.next().unwrap_or("").to_string();
+
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            if 0 == reader.read_line(&mut header)? || header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        let body = String::from_utf8_lossy(&body);
+
+        let (status, response) = route(&method, &path, &body, progress);
+        eprintln!("{} {} -> {}", method, path, status);
+
+        let mut stream = stream;
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nAccess-Control-Allow-Origin: *\r\nAccess-Control-Allow-Methods: GET, PUT, OPTIONS\r\nAccess-Control-Allow-Headers: Content-Type\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status, reason(status), response.len(), response,
+        )
+    }
+
+    fn route(method: &str, path: &str, body: &str, progress: &Progress) -> (u16, String) {
+        let user_id = match path.strip_prefix("/progress/") {
+            Some(user_id) if !user_id.is_empty() => user_id.to_string(),
+            _ => return (404, "".to_string()),
+        };
+        let mut progress = progress.lock().unwrap();
+
+        match method {
+            // ブラウザからの preflight
+            "OPTIONS" => (204, "".to_string()),
+            "GET" => match progress.get(&user_id) {
+                Some(snapshot) => (200, serde_json::to_string(snapshot).unwrap()),
+                None => (404, "".to_string()),
+            },
+            "PUT" => {
+                let snapshot = match serde_json::from_str::<Snapshot>(body) {
+                    Ok(snapshot) => snapshot,
+                    Err(_) => return (400, "".to_string()),
+                };
+                let revision = progress.get(&user_id).map_or(0, |saved| saved.revision);
+                if snapshot.revision != revision {
+                    return (409, "".to_string());
+                }
+
+                let saved = Snapshot {
+                    revision: revision + 1,
+                    payload: snapshot.payload,
+                };
+                let response = serde_json::to_string(&Snapshot { revision: saved.revision, payload: "".to_string() }).unwrap();
+                progress.insert(user_id, saved);
+
+                (200, response)
+            },
+            _ => (405, "".to_string()),
+        }
+    }
+
+    fn reason(status: u16) -> &'static str {
+        match status {
+            200 => "OK",
+            204 => "No Content",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            _ => "",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn start_server() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || mock_server::serve(listener));
+
+        endpoint
+    }
+
+    #[test]
+    fn pull_before_push_is_none() {
+        let mut backend = HttpSyncBackend::new(&start_server(), "nobody");
+
+        assert_eq!(block_on(backend.pull()), Ok(None));
+    }
+
+    #[test]
+    fn push_with_stale_revision_conflicts() {
+        let endpoint = start_server();
+        let mut phone = HttpSyncBackend::new(&endpoint, "humi");
+        let mut laptop = HttpSyncBackend::new(&endpoint, "humi");
+
+        assert_eq!(block_on(phone.push(&Snapshot { revision: 0, payload: "phone".to_string() })), Ok(1));
+        assert_eq!(block_on(laptop.push(&Snapshot { revision: 0, payload: "laptop".to_string() })), Err(SyncError::Conflict));
+        assert_eq!(block_on(laptop.pull()).unwrap().unwrap(), Snapshot { revision: 1, payload: "phone".to_string() });
+    }
+
+    #[test]
+    fn synchronize_merges_remote_progress() {
+        let endpoint = start_server();
+        let mut phone = HttpSyncBackend::new(&endpoint, "humi");
+        let mut laptop = HttpSyncBackend::new(&endpoint, "humi");
+
+        block_on(synchronize(&mut phone, |_| "a".to_string())).unwrap();
+        let merged = block_on(synchronize(&mut laptop, |remote| format!("{}b", remote.unwrap_or("")))).unwrap();
+
+        assert_eq!(merged, "ab");
+        assert_eq!(block_on(phone.pull()).unwrap().unwrap(), Snapshot { revision: 2, payload: "ab".to_string() });
+    }
+
+    /// 最初の push の直前に、別の端末 (interrupter) が先に push する同期先
+    struct InterruptedBackend {
+        backend: HttpSyncBackend,
+        interrupter: Option<HttpSyncBackend>,
+    }
+    impl SyncBackend for InterruptedBackend {
+        async fn pull(&mut self) -> Result<Option<Snapshot>, SyncError> {
+            self.backend.pull().await
+        }
+
+        async fn push(&mut self, snapshot: &Snapshot) -> Result<u64, SyncError> {
+            if let Some(mut interrupter) = self.interrupter.take() {
+                interrupter.push(&Snapshot { revision: 0, payload: "a".to_string() }).await.unwrap();
+            }
+            self.backend.push(snapshot).await
+        }
+    }
+
+    /// merge している間に別の端末が push すると、pull からやり直して両方の内容を残す
+    #[test]
+    fn synchronize_retries_after_conflict() {
+        let endpoint = start_server();
+        let mut laptop = InterruptedBackend {
+            backend: HttpSyncBackend::new(&endpoint, "humi"),
+            interrupter: Some(HttpSyncBackend::new(&endpoint, "humi")),
+        };
+
+        let merged = block_on(synchronize(&mut laptop, |remote| format!("{}b", remote.unwrap_or("")))).unwrap();
+
+        assert_eq!(merged, "ab");
+    }
+}
@@ -0,0 +1,261 @@
+//! チュートリアル
+//! ステージ毎の手順を tutorials.json に書き、ゲームで起きた出来事 (TutorialEvent) で手順を進める
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::collections::HashMap;
+
+/// 手順で見せるもの
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TutorialAction {
+    // { "type": "point_at_op_ruby" }: 演算のルビを指す
+    PointAtOpRuby,
+    // { "type": "drag_mark_to_sen", "sen": 2 }: 温泉マークを線へ動かす (sen は 1 から、無ければ線を順に指す)
+    DragMarkToSen {
+        #[serde(default)]
+        sen: Option<usize>,
+    },
+    // { "type": "show_text" }: 文章だけ
+    ShowText,
+}
+
+/// ゲームで起きた出来事
+#[derive(Clone, Debug, PartialEq)]
+pub enum TutorialEvent {
+    OpChanged { op: String },                       // 演算を切り替えた (op は小文字の名前)
+    SenChanged { sen: Option<usize>, op: String },  // 演算で線が変わった (sen は 0 から、全ての線なら None)
+    StageCleared,
+    Dismissed,                                      // 文章を閉じた
+}
+
+/// 手順を終える条件 (op, sen が無ければどれでも良い)
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TutorialCondition {
+    // { "type": "op_changed", "op": "not" }
+    OpChanged {
+        #[serde(default)]
+        op: Option<String>,
+    },
+    // { "type": "sen_changed", "sen": 2, "op": "not" }: sen は 1 から
+    SenChanged {
+        #[serde(default)]
+        sen: Option<usize>,
+        #[serde(default)]
+        op: Option<String>,
+    },
+    // { "type": "stage_cleared" }
+    StageCleared,
+    // { "type": "dismissed" }
+    Dismissed,
+}
+impl TutorialCondition {
+    pub fn matches(&self, event: &TutorialEvent) -> bool {
+        let matches_op = |expected: &Option<String>, op: &str| expected.as_ref().is_none_or(|expected| expected == op);
+
+        match (self, event) {
+            (Self::OpChanged { op: expected }, TutorialEvent::OpChanged { op }) => matches_op(expected, op),
+            (Self::SenChanged { sen: expected_sen, op: expected_op }, TutorialEvent::SenChanged { sen, op }) => {
+                expected_sen.is_none_or(|expected_sen| Some(expected_sen) == sen.map(|index| index + 1))
+                    && matches_op(expected_op, op)
+            },
+            (Self::StageCleared, TutorialEvent::StageCleared) => true,
+            (Self::Dismissed, TutorialEvent::Dismissed) => true,
+            _ => false,
+        }
+    }
+}
+
+/// チュートリアルの一つの手順
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TutorialStep {
+    pub action: TutorialAction,
+    #[serde(default)]
+    pub text: Option<String>,
+    // どれかが起きると次の手順へ進む (無ければ文章を閉じると進み、文章も無ければすぐに進む)
+    #[serde(default)]
+    pub complete_on: Vec<TutorialCondition>,
+}
+impl TutorialStep {
+    fn is_completed_by(&self, event: &TutorialEvent) -> bool {
+        if self.complete_on.is_empty() {
+            return TutorialEvent::Dismissed == *event;
+        }
+
+        self.complete_on.iter().any(|condition| condition.matches(event))
+    }
+
+    /// 文章も終える条件も無い手順は閉じようがないので、始めたらすぐに終える
+    fn is_completed_immediately(&self) -> bool {
+        self.text.as_deref().is_none_or(str::is_empty) && self.complete_on.is_empty()
+    }
+}
+
+/// ステージキー毎の手順 (tutorials.json)
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TutorialBook {
+    #[serde(flatten)]
+    pub scripts: HashMap<String, Vec<TutorialStep>>,
+}
+
+/// 進めているチュートリアル
+#[derive(Clone, Debug)]
+pub struct TutorialRunner {
+    name: String,
+    steps: Vec<TutorialStep>,
+    index: usize,
+}
+impl TutorialRunner {
+    pub fn new(name: &str, steps: Vec<TutorialStep>) -> Self {
+        let mut runner = Self {
+            name: name.to_string(),
+            steps,
+            index: 0,
+        };
+        runner.skip_completed_steps();
+
+        runner
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 今の手順、全て終えていれば None
+    pub fn current(&self) -> Option<&TutorialStep> {
+        self.steps.get(self.index)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.steps.len() <= self.index
+    }
+
+    /// 出来事で今の手順を終えたら次へ進めて true を返す
+    pub fn observe(&mut self, event: &TutorialEvent) -> bool {
+        match self.current() {
+            Some(step) if step.is_completed_by(event) => {
+                self.index += 1;
+                self.skip_completed_steps();
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn skip_completed_steps(&mut self) {
+        while self.current().is_some_and(TutorialStep::is_completed_immediately) {
+            self.index += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op_changed(op: &str) -> TutorialEvent {
+        TutorialEvent::OpChanged { op: op.to_string() }
+    }
+
+    fn sen_changed(sen: Option<usize>, op: &str) -> TutorialEvent {
+        TutorialEvent::SenChanged { sen, op: op.to_string() }
+    }
+
+    fn step(action: TutorialAction, text: Option<&str>, complete_on: Vec<TutorialCondition>) -> TutorialStep {
+        TutorialStep {
+            action,
+            text: text.map(str::to_string),
+            complete_on,
+        }
+    }
+
+    #[test]
+    fn op_changed_matches_op() {
+        let any_op = TutorialCondition::OpChanged { op: None };
+        let not = TutorialCondition::OpChanged { op: Some("not".to_string()) };
+
+        assert!(any_op.matches(&op_changed("or")));
+        assert!(not.matches(&op_changed("not")));
+        assert!(!not.matches(&op_changed("or")));
+        assert!(!not.matches(&sen_changed(Some(0), "not")));
+    }
+
+    #[test]
+    fn sen_changed_counts_sen_from_one() {
+        let second_sen = TutorialCondition::SenChanged { sen: Some(2), op: None };
+
+        assert!(second_sen.matches(&sen_changed(Some(1), "not")));
+        assert!(!second_sen.matches(&sen_changed(Some(2), "not")));
+        // 全ての線を変えた時は、線を指定した条件には当てはまらない
+        assert!(!second_sen.matches(&sen_changed(None, "on")));
+    }
+
+    #[test]
+    fn sen_changed_matches_op() {
+        let any_sen = TutorialCondition::SenChanged { sen: None, op: Some("on".to_string()) };
+
+        assert!(any_sen.matches(&sen_changed(None, "on")));
+        assert!(any_sen.matches(&sen_changed(Some(0), "on")));
+        assert!(!any_sen.matches(&sen_changed(Some(0), "not")));
+    }
+
+    #[test]
+    fn unit_conditions_match_same_event() {
+        assert!(TutorialCondition::StageCleared.matches(&TutorialEvent::StageCleared));
+        assert!(TutorialCondition::Dismissed.matches(&TutorialEvent::Dismissed));
+        assert!(!TutorialCondition::StageCleared.matches(&TutorialEvent::Dismissed));
+    }
+
+    #[test]
+    fn observe_advances_only_on_matching_event() {
+        let mut runner = TutorialRunner::new("top", vec![
+            step(TutorialAction::PointAtOpRuby, Some("演算を変える"), vec![TutorialCondition::OpChanged { op: Some("not".to_string()) }]),
+            step(TutorialAction::DragMarkToSen { sen: None }, Some("線へ動かす"), vec![TutorialCondition::SenChanged { sen: None, op: None }]),
+        ]);
+
+        assert!(!runner.observe(&op_changed("or")));
+        assert_eq!(runner.current().unwrap().action, TutorialAction::PointAtOpRuby);
+        assert!(runner.observe(&op_changed("not")));
+        assert_eq!(runner.current().unwrap().action, TutorialAction::DragMarkToSen { sen: None });
+        assert!(runner.observe(&sen_changed(Some(0), "not")));
+        assert!(runner.is_finished());
+        assert!(!runner.observe(&TutorialEvent::StageCleared));
+    }
+
+    #[test]
+    fn text_step_without_conditions_waits_for_dismiss() {
+        let mut runner = TutorialRunner::new("top", vec![step(TutorialAction::ShowText, Some("ようこそ"), vec![])]);
+
+        assert!(!runner.observe(&op_changed("not")));
+        assert!(runner.observe(&TutorialEvent::Dismissed));
+        assert!(runner.is_finished());
+    }
+
+    #[test]
+    fn step_without_text_and_conditions_is_skipped() {
+        let runner = TutorialRunner::new("top", vec![step(TutorialAction::ShowText, None, vec![])]);
+        assert!(runner.is_finished());
+
+        let mut runner = TutorialRunner::new("top", vec![
+            step(TutorialAction::PointAtOpRuby, None, vec![TutorialCondition::OpChanged { op: None }]),
+            step(TutorialAction::ShowText, Some(""), vec![]),
+        ]);
+        assert!(runner.observe(&op_changed("on")));
+        assert!(runner.is_finished());
+    }
+
+    #[test]
+    fn book_reads_steps_per_stage() {
+        let book: TutorialBook = serde_json::from_str(r#"{
+            "top": [
+                { "action": { "type": "drag_mark_to_sen", "sen": 2 }, "text": "線へ", "complete_on": [{ "type": "sen_changed", "sen": 2 }] }
+            ]
+        }"#).unwrap();
+
+        assert_eq!(book.scripts["top"], vec![
+            step(TutorialAction::DragMarkToSen { sen: Some(2) }, Some("線へ"), vec![TutorialCondition::SenChanged { sen: Some(2), op: None }]),
+        ]);
+    }
+}
@@ -0,0 +1,218 @@
+//! ステージやレベルを開く条件
+//! 条件は quastions.json に書き、進行状況 (Progress) と比べて開いているかを決める
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+/// 開く条件の一つ
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UnlockRule {
+    // { "type": "clear_stage", "stage": "stage_1_3" }
+    ClearStage { stage: String },
+    // { "type": "clear_level", "level": 0 }: レベルのステージを全てクリアする
+    ClearLevel { level: i32 },
+    // { "type": "collect_stars", "count": 8, "level": 1 }: level が無ければ全てのステージで数える
+    CollectStars {
+        count: i32,
+        #[serde(default)]
+        level: Option<i32>,
+    },
+    // { "type": "discover_names", "count": 5 }: 図鑑の温泉名の数
+    DiscoverNames { count: usize },
+}
+
+/// 条件と比べる進行状況
+pub trait Progress {
+    /// ステージをクリアしているか
+    fn is_stage_cleared(&self, key: &str) -> bool;
+    /// レベルのステージキー
+    fn level_stage_keys(&self, level: i32) -> Vec<String>;
+    /// 獲得した☆の数 (level が None なら全てのステージ)
+    fn star_count(&self, level: Option<i32>) -> i32;
+    /// 見つけた温泉名の数
+    fn discovered_name_count(&self) -> usize;
+    /// 説明に使うステージの名前
+    fn stage_label(&self, key: &str) -> String;
+}
+
+impl UnlockRule {
+    pub fn is_satisfied<P: Progress>(&self, progress: &P) -> bool {
+        match self {
+            Self::ClearStage { stage } => progress.is_stage_cleared(stage),
+            Self::ClearLevel { level } => progress.level_stage_keys(*level).iter().all(|key| progress.is_stage_cleared(key)),
+            Self::CollectStars { count, level } => *count <= progress.star_count(*level),
+            Self::DiscoverNames { count } => *count <= progress.discovered_name_count(),
+        }
+    }
+
+    /// まだ足りないものの説明 ("☆をあと 3 個集める" など)
+    pub fn describe_remaining<P: Progress>(&self, progress: &P) -> String {
+        match self {
+            Self::ClearStage { stage } => format!("{} をクリアする", progress.stage_label(stage)),
+            Self::ClearLevel { level } => {
+                let keys = progress.level_stage_keys(*level);
+                let remaining = keys.iter().filter(|key| !progress.is_stage_cleared(key)).count();
+                format!("Lv.{} のステージをあと {} つクリアする", level + 1, remaining)
+            },
+            Self::CollectStars { count, level } => {
+                let remaining = count - progress.star_count(*level);
+                match level {
+                    Some(level) => format!("Lv.{} で☆をあと {} 個集める", level + 1, remaining),
+                    None => format!("☆をあと {} 個集める", remaining),
+                }
+            },
+            Self::DiscoverNames { count } => {
+                format!("温泉名をあと {} 個見つける", count.saturating_sub(progress.discovered_name_count()))
+            },
+        }
+    }
+}
+
+/// 満たしていない条件の説明を返す、全て満たしていれば空
+pub fn missing_requirements<P: Progress>(rules: &[UnlockRule], progress: &P) -> Vec<String> {
+    rules.iter()
+        .filter(|rule| !rule.is_satisfied(progress))
+        .map(|rule| rule.describe_remaining(progress))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lv.1 (level 0) に stage_0_1, stage_0_2 があり、stage_0_1 だけクリアしている
+    struct FakeProgress {
+        cleared: Vec<&'static str>,
+        stars: i32,
+        level_stars: i32,
+        discovered: usize,
+    }
+    impl Progress for FakeProgress {
+        fn is_stage_cleared(&self, key: &str) -> bool {
+            self.cleared.contains(&key)
+        }
+
+        fn level_stage_keys(&self, level: i32) -> Vec<String> {
+            match level {
+                0 => vec!["stage_0_1".to_string(), "stage_0_2".to_string()],
+                _ => vec![],
+            }
+        }
+
+        fn star_count(&self, level: Option<i32>) -> i32 {
+            match level {
+                Some(_) => self.level_stars,
+                None => self.stars,
+            }
+        }
+
+        fn discovered_name_count(&self) -> usize {
+            self.discovered
+        }
+
+        fn stage_label(&self, key: &str) -> String {
+            format!("ステージ {}", key.trim_start_matches("stage_"))
+        }
+    }
+
+    fn progress() -> FakeProgress {
+        FakeProgress {
+            cleared: vec!["stage_0_1"],
+            stars: 5,
+            level_stars: 2,
+            discovered: 3,
+        }
+    }
+
+    #[test]
+    fn clear_stage() {
+        let progress = progress();
+
+        assert!(UnlockRule::ClearStage { stage: "stage_0_1".to_string() }.is_satisfied(&progress));
+        let rule = UnlockRule::ClearStage { stage: "stage_0_2".to_string() };
+        assert!(!rule.is_satisfied(&progress));
+        assert_eq!(rule.describe_remaining(&progress), "ステージ 0_2 をクリアする");
+    }
+
+    #[test]
+    fn clear_level() {
+        let mut progress = progress();
+        let rule = UnlockRule::ClearLevel { level: 0 };
+
+        assert!(!rule.is_satisfied(&progress));
+        assert_eq!(rule.describe_remaining(&progress), "Lv.1 のステージをあと 1 つクリアする");
+
+        progress.cleared.push("stage_0_2");
+        assert!(rule.is_satisfied(&progress));
+    }
+
+    #[test]
+    fn collect_stars() {
+        let progress = progress();
+
+        assert!(UnlockRule::CollectStars { count: 5, level: None }.is_satisfied(&progress));
+        let rule = UnlockRule::CollectStars { count: 8, level: None };
+        assert!(!rule.is_satisfied(&progress));
+        assert_eq!(rule.describe_remaining(&progress), "☆をあと 3 個集める");
+    }
+
+    #[test]
+    fn collect_stars_in_level() {
+        let progress = progress();
+
+        assert!(UnlockRule::CollectStars { count: 2, level: Some(1) }.is_satisfied(&progress));
+        let rule = UnlockRule::CollectStars { count: 6, level: Some(1) };
+        assert!(!rule.is_satisfied(&progress));
+        assert_eq!(rule.describe_remaining(&progress), "Lv.2 で☆をあと 4 個集める");
+    }
+
+    #[test]
+    fn discover_names() {
+        let progress = progress();
+
+        assert!(UnlockRule::DiscoverNames { count: 3 }.is_satisfied(&progress));
+        let rule = UnlockRule::DiscoverNames { count: 5 };
+        assert!(!rule.is_satisfied(&progress));
+        assert_eq!(rule.describe_remaining(&progress), "温泉名をあと 2 個見つける");
+    }
+
+    #[test]
+    fn missing_requirements_lists_only_unsatisfied_rules() {
+        let rules = [
+            UnlockRule::ClearStage { stage: "stage_0_1".to_string() },
+            UnlockRule::CollectStars { count: 8, level: None },
+            UnlockRule::DiscoverNames { count: 5 },
+        ];
+
+        assert_eq!(missing_requirements(&rules, &progress()), vec!["☆をあと 3 個集める", "温泉名をあと 2 個見つける"]);
+        assert!(missing_requirements(&rules[..1], &progress()).is_empty());
+    }
+
+    #[test]
+    fn no_rules_means_unlocked() {
+        // quastions.json に条件が書かれていないステージやレベルは最初から開いている
+        let progress = FakeProgress {
+            cleared: vec![],
+            stars: 0,
+            level_stars: 0,
+            discovered: 0,
+        };
+
+        assert!(missing_requirements(&[], &progress).is_empty());
+    }
+
+    #[test]
+    fn rules_are_read_from_json() {
+        let rules: Vec<UnlockRule> = serde_json::from_str(r#"[
+            { "type": "clear_stage", "stage": "stage_1_3" },
+            { "type": "collect_stars", "count": 8 }
+        ]"#).unwrap();
+
+        assert_eq!(rules, vec![
+            UnlockRule::ClearStage { stage: "stage_1_3".to_string() },
+            UnlockRule::CollectStars { count: 8, level: None },
+        ]);
+    }
+}